@@ -1,7 +1,6 @@
 //! Handle `cargo add` arguments
 
-use cargo_edit::{find, registry_url, Dependency};
-use cargo_edit::{get_latest_dependency, CrateName};
+use cargo_edit::{get_latest_dependency, CrateName, Dependency, GitReference, RegistryIndex};
 use semver;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -14,14 +13,33 @@ pub enum Command {
     /// Add dependency to a Cargo.toml manifest file.
     #[structopt(name = "add")]
     #[structopt(
-        after_help = "This command allows you to add a dependency to a Cargo.toml manifest file. If <crate> is a github
-or gitlab repository URL, or a local path, `cargo add` will try to automatically get the crate name
-and set the appropriate `--git` or `--path` value.
+        after_help = "This command allows you to add a dependency to a Cargo.toml manifest file. If <crate> is the URL
+of a git repository (e.g. `https://`, `ssh://` or `git@host:path`), or a local path, `cargo add`
+will try to automatically get the crate name and set the appropriate `--git` or `--path` value.
 
 Please note that Cargo treats versions like '1.2.3' as '^1.2.3' (and that '^1.2.3' is specified
 as '>=1.2.3 and <2.0.0'). By default, `cargo add` will use this format, as it is the one that the
 crates.io registry suggests. One goal of `cargo add` is to prevent you from using wildcard
-dependencies (version set to '*')."
+dependencies (version set to '*').
+
+Registries whose index URL starts with `sparse+` are queried over HTTP with the sparse protocol,
+as is crates.io when `registries.crates-io.protocol` is set to 'sparse' in the cargo config.
+Registries replaced with a `local-registry` or `directory` source are read from that source.
+Git indices are fetched with libgit2, using the proxy of the `https_proxy`-style environment
+variables, the SSH agent and git credential helpers, or with the `git` executable if
+`net.git-fetch-with-cli` is set. While fetching, the package cache of cargo is locked, so
+concurrent runs of cargo and `cargo add` wait for each other.
+
+Offline, versions are looked up in the registry index as downloaded before, preferring the
+versions whose crate files have been downloaded already, and crate names are not read from
+git repositories.
+
+The cargo config is read from `.cargo/config.toml` (or `.cargo/config`) files, `--config` arguments
+and `CARGO_REGISTRIES_<NAME>_INDEX`-style environment variables. Without `--registry`, crates are
+added from the registry set with `registry.default` (or `CARGO_REGISTRY_DEFAULT`), if any.
+Registry tokens are read from `credentials.toml` in the cargo home directory,
+`CARGO_REGISTRIES_<NAME>_TOKEN` and `CARGO_REGISTRY_TOKEN`. They are sent with requests to sparse
+indices, and offered as password to git indices asking for credentials."
     )]
     Add(Args),
 }
@@ -61,6 +79,36 @@ pub struct Args {
     )]
     pub git: Option<String>,
 
+    /// Specify a git branch to download the crate from.
+    #[structopt(
+        long = "branch",
+        value_name = "branch",
+        requires = "git",
+        conflicts_with = "tag",
+        conflicts_with = "rev"
+    )]
+    pub branch: Option<String>,
+
+    /// Specify a git tag to download the crate from.
+    #[structopt(
+        long = "tag",
+        value_name = "tag",
+        requires = "git",
+        conflicts_with = "branch",
+        conflicts_with = "rev"
+    )]
+    pub tag: Option<String>,
+
+    /// Specify a git revision to download the crate from.
+    #[structopt(
+        long = "rev",
+        value_name = "rev",
+        requires = "git",
+        conflicts_with = "branch",
+        conflicts_with = "tag"
+    )]
+    pub rev: Option<String>,
+
     /// Specify the path the crate should be loaded from.
     #[structopt(long = "path", conflicts_with = "git")]
     pub path: Option<PathBuf>,
@@ -99,21 +147,41 @@ pub struct Args {
     #[structopt(long = "no-default-features")]
     pub no_default_features: bool,
 
+    /// Comma- or space-separated list of features to enable on the added dependency.
+    /// Features are added to the ones already enabled if the dependency exists.
+    #[structopt(long = "features", value_name = "features", number_of_values = 1)]
+    pub features: Vec<String>,
+
+    /// Add the crate to `[workspace.dependencies]` of the workspace root and inherit it with
+    /// `workspace = true`. Features and `--optional` are set on the inheriting entry.
+    #[structopt(long = "workspace-dep")]
+    pub workspace_dep: bool,
+
     /// Do not print any output in case of success.
     #[structopt(long = "quiet", short = "q")]
     pub quiet: bool,
 
-    /// Run without accessing the network
+    /// Run without accessing the network. Also set with `net.offline` or `CARGO_NET_OFFLINE`.
     #[structopt(long = "offline")]
     pub offline: bool,
 
+    /// Do not fetch registry indices that have been fetched before. By default, an index is
+    /// fetched again once it is older than `CARGO_EDIT_INDEX_FRESHNESS` seconds (60 if unset).
+    #[structopt(long = "no-update")]
+    pub no_update: bool,
+
     /// Keep dependencies sorted
     #[structopt(long = "sort", short = "s")]
     pub sort: bool,
 
-    /// Registry to use
+    /// Registry to use. Defaults to `registry.default` of the cargo config, or crates.io.
     #[structopt(long = "registry", conflicts_with = "git", conflicts_with = "path")]
     pub registry: Option<String>,
+
+    /// Override a cargo config value, given as `KEY=VALUE` in TOML syntax or as the path to an
+    /// additional config file.
+    #[structopt(long = "config", value_name = "config", number_of_values = 1)]
+    pub config: Vec<String>,
 }
 
 fn parse_version_req(s: &str) -> Result<&str> {
@@ -142,7 +210,13 @@ impl Args {
         }
     }
 
-    fn parse_single_dependency(&self, crate_name: &str) -> Result<Dependency> {
+    fn parse_single_dependency(
+        &self,
+        crate_name: &str,
+        index: &dyn RegistryIndex,
+        registry: Option<&str>,
+        offline: bool,
+    ) -> Result<Dependency> {
         let crate_name = CrateName::new(crate_name);
 
         if let Some(mut dependency) = crate_name.parse_as_version()? {
@@ -155,11 +229,13 @@ impl Args {
 
             if let Some(ref path) = self.path {
                 dependency = dependency.set_path(path.to_str().unwrap());
+            } else if let Some(registry) = registry {
+                dependency = dependency.set_registry(registry);
             }
 
             Ok(dependency)
         } else if crate_name.is_url_or_path() {
-            Ok(crate_name.parse_crate_name_from_uri()?)
+            Ok(crate_name.parse_crate_name_from_uri(offline)?)
         } else {
             assert_eq!(self.git.is_some() && self.vers.is_some(), false);
             assert_eq!(self.git.is_some() && self.path.is_some(), false);
@@ -170,6 +246,9 @@ impl Args {
 
             if let Some(repo) = &self.git {
                 dependency = dependency.set_git(repo);
+                if let Some(reference) = self.get_git_reference() {
+                    dependency = dependency.set_git_reference(reference);
+                }
             }
             if let Some(path) = &self.path {
                 dependency = dependency.set_path(path.to_str().unwrap());
@@ -177,19 +256,8 @@ impl Args {
             if let Some(version) = &self.vers {
                 dependency = dependency.set_version(parse_version_req(version)?);
             }
-            let registry_url = if let Some(registry) = &self.registry {
-                Some(registry_url(&find(&self.manifest_path)?, Some(registry))?)
-            } else {
-                None
-            };
-
             if self.git.is_none() && self.path.is_none() && self.vers.is_none() {
-                let dep = get_latest_dependency(
-                    crate_name.name(),
-                    self.allow_prerelease,
-                    &find(&self.manifest_path)?,
-                    &registry_url,
-                )?;
+                let dep = get_latest_dependency(crate_name.name(), self.allow_prerelease, index)?;
                 let v = format!(
                     "{prefix}{version}",
                     prefix = self.get_upgrade_prefix(),
@@ -202,16 +270,24 @@ impl Args {
 
             // Set the registry after getting the latest version as
             // get_latest_dependency returns a registry-less Dependency
-            if let Some(registry) = &self.registry {
-                dependency = dependency.set_registry(registry);
+            if let Some(registry) = registry {
+                if self.git.is_none() && self.path.is_none() {
+                    dependency = dependency.set_registry(registry);
+                }
             }
 
             Ok(dependency)
         }
     }
 
-    /// Build dependencies from arguments
-    pub fn parse_dependencies(&self) -> Result<Vec<Dependency>> {
+    /// Build dependencies from arguments, looking up versions not given in `index`, the index of
+    /// `registry` (`None` for crates.io). Nothing is downloaded when `offline`.
+    pub fn parse_dependencies(
+        &self,
+        index: &dyn RegistryIndex,
+        registry: Option<&str>,
+        offline: bool,
+    ) -> Result<Vec<Dependency>> {
         if self.crates.len() > 1
             && (self.git.is_some() || self.path.is_some() || self.vers.is_some())
         {
@@ -222,22 +298,54 @@ impl Args {
             return Err(ErrorKind::MultipleCratesWithRename.into());
         }
 
+        if self.crates.len() > 1 && !self.features.is_empty() {
+            return Err(ErrorKind::MultipleCratesWithFeatures.into());
+        }
+
         self.crates
             .iter()
             .map(|crate_name| {
-                self.parse_single_dependency(crate_name).map(|x| {
-                    let mut x = x
-                        .set_optional(self.optional)
-                        .set_default_features(!self.no_default_features);
-                    if let Some(ref rename) = self.rename {
-                        x = x.set_rename(rename);
-                    }
-                    x
-                })
+                self.parse_single_dependency(crate_name, index, registry, offline)
+                    .map(|x| {
+                        let mut x = x
+                            .set_optional(self.optional)
+                            .set_default_features(!self.no_default_features);
+                        if let Some(ref rename) = self.rename {
+                            x = x.set_rename(rename);
+                        }
+                        if let Some(features) = self.get_features() {
+                            x = x.set_features(Some(features));
+                        }
+                        x
+                    })
             })
             .collect()
     }
 
+    /// Get the branch, tag or revision to use for a git dependency
+    fn get_git_reference(&self) -> Option<GitReference> {
+        self.branch
+            .clone()
+            .map(GitReference::Branch)
+            .or_else(|| self.tag.clone().map(GitReference::Tag))
+            .or_else(|| self.rev.clone().map(GitReference::Rev))
+    }
+
+    /// Get the features to enable, splitting each `--features` value on commas and spaces.
+    fn get_features(&self) -> Option<Vec<String>> {
+        if self.features.is_empty() {
+            return None;
+        }
+        Some(
+            self.features
+                .iter()
+                .flat_map(|f| f.split(|c: char| c == ',' || c.is_whitespace()))
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+                .collect(),
+        )
+    }
+
     fn get_upgrade_prefix(&self) -> &'static str {
         match self.upgrade.as_ref() {
             "default" => "",
@@ -260,6 +368,9 @@ impl Default for Args {
             build: false,
             vers: None,
             git: None,
+            branch: None,
+            tag: None,
+            rev: None,
             path: None,
             target: None,
             optional: false,
@@ -267,10 +378,14 @@ impl Default for Args {
             upgrade: "minor".to_string(),
             allow_prerelease: false,
             no_default_features: false,
+            features: vec![],
+            workspace_dep: false,
             quiet: false,
             offline: true,
+            no_update: false,
             sort: false,
             registry: None,
+            config: vec![],
         }
     }
 }
@@ -278,7 +393,7 @@ impl Default for Args {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cargo_edit::Dependency;
+    use cargo_edit::{CrateVersion, Dependency, MemoryIndex};
 
     #[test]
     fn test_dependency_parsing() {
@@ -288,11 +403,69 @@ mod tests {
         };
 
         assert_eq!(
-            args.parse_dependencies().unwrap(),
+            args.parse_dependencies(&MemoryIndex::new(), None, false)
+                .unwrap(),
             vec![Dependency::new("demo").set_version("0.4.2")]
         );
     }
 
+    #[test]
+    fn test_latest_version_parsing() {
+        let mut index = MemoryIndex::new();
+        for (version, yanked) in &[("0.1.0", false), ("0.2.0", false), ("0.3.0", true)] {
+            index.insert(CrateVersion {
+                name: "demo".to_owned(),
+                version: semver::Version::parse(version).unwrap(),
+                yanked: *yanked,
+            });
+        }
+        let args = Args::default();
+
+        assert_eq!(
+            args.parse_dependencies(&index, None, false).unwrap(),
+            vec![Dependency::new("demo").set_version("^0.2.0")]
+        );
+    }
+
+    #[test]
+    fn test_dependency_with_features_parsing() {
+        let args = Args {
+            vers: Some("0.2".to_owned()),
+            features: vec!["rt,macros".to_owned(), "net io".to_owned()],
+            ..Args::default()
+        };
+
+        assert_eq!(
+            args.parse_dependencies(&MemoryIndex::new(), None, false)
+                .unwrap(),
+            vec![Dependency::new("demo")
+                .set_version("0.2")
+                .set_features(Some(vec![
+                    "rt".to_owned(),
+                    "macros".to_owned(),
+                    "net".to_owned(),
+                    "io".to_owned()
+                ]))]
+        );
+    }
+
+    #[test]
+    fn test_git_dependency_with_branch_parsing() {
+        let args = Args {
+            git: Some("https://localhost/demo.git".to_owned()),
+            branch: Some("dev".to_owned()),
+            ..Args::default()
+        };
+
+        assert_eq!(
+            args.parse_dependencies(&MemoryIndex::new(), None, false)
+                .unwrap(),
+            vec![Dependency::new("demo")
+                .set_git("https://localhost/demo.git")
+                .set_git_reference(GitReference::Branch("dev".to_owned()))]
+        );
+    }
+
     #[test]
     #[cfg(feature = "test-external-apis")]
     fn test_repo_as_arg_parsing() {
@@ -302,7 +475,9 @@ mod tests {
             ..Args::default()
         };
         assert_eq!(
-            args_github.parse_dependencies().unwrap(),
+            args_github
+                .parse_dependencies(&MemoryIndex::new(), None, false)
+                .unwrap(),
             vec![Dependency::new("cargo-edit").set_git(github_url)]
         );
 
@@ -312,7 +487,9 @@ mod tests {
             ..Args::default()
         };
         assert_eq!(
-            args_gitlab.parse_dependencies().unwrap(),
+            args_gitlab
+                .parse_dependencies(&MemoryIndex::new(), None, false)
+                .unwrap(),
             vec![Dependency::new("polly").set_git(gitlab_url)]
         );
     }
@@ -325,7 +502,9 @@ mod tests {
             ..Args::default()
         };
         assert_eq!(
-            args_path.parse_dependencies().unwrap(),
+            args_path
+                .parse_dependencies(&MemoryIndex::new(), None, false)
+                .unwrap(),
             vec![Dependency::new("cargo-edit").set_path(self_path)]
         );
     }
@@ -15,12 +15,13 @@
 extern crate error_chain;
 
 use crate::args::{Args, Command};
-use cargo_edit::{find, registry_url, update_registry_index, Dependency, Manifest};
+use cargo_edit::{find, find_workspace_root, registry_index, CargoConfig, Dependency, Manifest};
+use std::fs;
 use std::io::Write;
+use std::path::{Component, Path, PathBuf};
 use std::process;
 use structopt::StructOpt;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-use toml_edit::Item as TomlItem;
 
 mod args;
 
@@ -42,6 +43,11 @@ mod errors {
                 description("Specified multiple crates with rename")
                 display("Cannot specify multiple crates with rename")
             }
+            /// Specified multiple crates with features.
+            MultipleCratesWithFeatures {
+                description("Specified multiple crates with features")
+                display("Cannot specify multiple crates with features")
+            }
         }
         links {
             CargoEditLib(::cargo_edit::Error, ::cargo_edit::ErrorKind);
@@ -53,7 +59,7 @@ mod errors {
 }
 use crate::errors::*;
 
-fn print_msg(dep: &Dependency, section: &[String], optional: bool) -> Result<()> {
+fn print_msg(dep: &Dependency, section: &[String]) -> Result<()> {
     let colorchoice = if atty::is(atty::Stream::Stdout) {
         ColorChoice::Auto
     } else {
@@ -64,57 +70,150 @@ fn print_msg(dep: &Dependency, section: &[String], optional: bool) -> Result<()>
     write!(output, "{:>12}", "Adding")?;
     output.reset()?;
     write!(output, " {}", dep.name)?;
-    if let Some(version) = dep.version() {
+    if dep.inherited() {
+        write!(output, " from workspace")?;
+    } else if let Some(version) = dep.version() {
         write!(output, " v{}", version)?;
     } else {
         write!(output, " (unknown version)")?;
     }
     write!(output, " to")?;
-    if optional {
+    if dep.optional() {
         write!(output, " optional")?;
     }
-    let section = if section.len() == 1 {
-        section[0].clone()
-    } else {
+    let section = if section.len() == 3 && section[0] == "target" {
         format!("{} for target `{}`", &section[2], &section[1])
+    } else {
+        section.join(".")
     };
     writeln!(output, " {}", section)?;
     Ok(())
 }
 
+/// Split a dependency into its entry in `[workspace.dependencies]` and the entry inheriting it.
+///
+/// Relative paths are given relative to the member, so they are rebased onto the workspace root.
+fn split_workspace_dependency(
+    dep: &Dependency,
+    member_dir: &Path,
+    root_dir: &Path,
+) -> (Dependency, Dependency) {
+    let mut root_dep = dep.clone().set_optional(false).set_features(None);
+    if let (Some(path), Ok(member)) = (dep.path(), member_dir.strip_prefix(root_dir)) {
+        if Path::new(path).is_relative() {
+            let path = normalize_path(&member.join(path));
+            root_dep = root_dep.set_path(&path.to_string_lossy());
+        }
+    }
+
+    let mut member_dep = Dependency::new(&dep.name)
+        .set_inherited()
+        .set_optional(dep.optional())
+        .set_features(dep.features().map(<[String]>::to_vec));
+    if let Some(rename) = dep.rename() {
+        member_dep = member_dep.set_rename(rename);
+    }
+
+    (root_dep, member_dep)
+}
+
+/// Resolve the `.` and `..` components of a relative path without touching the filesystem
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn add_to_table(
+    manifest: &mut Manifest,
+    section: &[String],
+    dep: &Dependency,
+    args: &Args,
+) -> Result<()> {
+    if !args.quiet {
+        print_msg(dep, section)?;
+    }
+    manifest.insert_into_table(section, dep)?;
+    if args.sort {
+        if let Some(table) = manifest.get_table(section)?.as_table_mut() {
+            table.sort_values();
+        }
+    }
+    Ok(())
+}
+
 fn handle_add(args: &Args) -> Result<()> {
     let manifest_path = &args.manifest_path;
     let mut manifest = Manifest::open(manifest_path)?;
-    let deps = &args.parse_dependencies()?;
-
-    if !args.offline {
-        let url = registry_url(
-            &find(&manifest_path)?,
-            args.registry.as_ref().map(String::as_ref),
-        )?;
-        update_registry_index(&url)?;
+
+    if manifest.is_virtual() && !args.workspace_dep {
+        return Err(cargo_edit::Error::from(cargo_edit::ErrorKind::UnexpectedRootManifest).into());
     }
 
+    let mut config = CargoConfig::load(&find(&manifest_path)?, &args.config)?;
+    if args.offline {
+        config.set_offline();
+    }
+    if args.no_update {
+        config.set_no_update();
+    }
+    let registry = args
+        .registry
+        .as_deref()
+        .or_else(|| config.default_registry())
+        .filter(|registry| *registry != "crates-io");
+    let index = registry_index(&config.registry_url(registry)?, &config)?;
+    index.update()?;
+    let deps = &args.parse_dependencies(index.as_ref(), registry, config.offline())?;
+
+    // The workspace root, unless it is the manifest we are adding to.
+    let mut workspace = None;
+    let (mut member_dir, mut root_dir) = (PathBuf::new(), PathBuf::new());
+    if args.workspace_dep {
+        let member_path = fs::canonicalize(find(manifest_path)?)?;
+        let root_path = find_workspace_root(&member_path)?;
+        member_dir = member_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        root_dir = root_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        if root_path != member_path {
+            workspace = Some((Manifest::open(&Some(root_path.clone()))?, root_path));
+        }
+    }
+    let workspace_section = vec!["workspace".to_owned(), "dependencies".to_owned()];
+
     deps.iter()
         .map(|dep| {
-            if !args.quiet {
-                print_msg(dep, &args.get_section(), args.optional)?;
+            if !args.workspace_dep {
+                return add_to_table(&mut manifest, &args.get_section(), dep, args);
             }
-            manifest
-                .insert_into_table(&args.get_section(), dep)
-                .map(|_| {
-                    manifest
-                        .get_table(&args.get_section())
-                        .map(TomlItem::as_table_mut)
-                        .map(|table_option| {
-                            table_option.map(|table| {
-                                if args.sort {
-                                    table.sort_values();
-                                }
-                            })
-                        })
-                })
-                .map_err(Into::into)
+
+            let (root_dep, member_dep) = split_workspace_dependency(dep, &member_dir, &root_dir);
+            let root = match workspace {
+                Some((ref mut root, _)) => root,
+                None => &mut manifest,
+            };
+            add_to_table(root, &workspace_section, &root_dep, args)?;
+            // A virtual manifest has no dependencies of its own to wire up.
+            if !manifest.is_virtual() {
+                add_to_table(&mut manifest, &args.get_section(), &member_dep, args)?;
+            }
+            Ok(())
         })
         .collect::<Result<Vec<_>>>()
         .map_err(|err| {
@@ -122,6 +221,10 @@ fn handle_add(args: &Args) -> Result<()> {
             err
         })?;
 
+    if let Some((root, root_path)) = workspace {
+        let mut file = Manifest::find_file(&Some(root_path))?;
+        root.write_to_file(&mut file)?;
+    }
     let mut file = Manifest::find_file(manifest_path)?;
     manifest.write_to_file(&mut file)?;
 
@@ -0,0 +1,581 @@
+//! `cargo consolidate`
+#![warn(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    trivial_casts,
+    trivial_numeric_casts,
+    unsafe_code,
+    unstable_features,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+#[macro_use]
+extern crate error_chain;
+
+use crate::errors::*;
+use cargo_edit::{Dependency, Manifest};
+use failure::Fail;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use structopt::StructOpt;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+mod errors {
+    error_chain! {
+        links {
+            CargoEditLib(::cargo_edit::Error, ::cargo_edit::ErrorKind);
+        }
+        foreign_links {
+            CargoMetadata(::failure::Compat<::cargo_metadata::Error>);
+            Io(::std::io::Error);
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(bin_name = "cargo")]
+enum Command {
+    /// Move dependencies shared by workspace members into `[workspace.dependencies]`.
+    #[structopt(name = "consolidate")]
+    #[structopt(
+        after_help = "Registry dependencies declared by at least `--min-members` packages of the workspace are added
+to `[workspace.dependencies]` of the workspace root, and the members are changed to inherit them with
+`workspace = true`. Features and `optional` stay with each member.
+
+The requirement written to the workspace root is the highest of the members' requirements. Crates
+whose requirements are not semver compatible (e.g. `0.2` and `0.3`) or use different operators
+(e.g. `1.0` and `~1.4`), whose members disagree on `default-features`, or whose key refers to
+different packages in different members, are skipped. Git/path dependencies are ignored."
+    )]
+    Consolidate(Args),
+}
+
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// Path to the manifest of the workspace root or of one of its members.
+    #[structopt(long = "manifest-path", value_name = "path")]
+    manifest_path: Option<PathBuf>,
+
+    /// Minimum number of members that must depend on a crate for it to be consolidated.
+    #[structopt(long = "min-members", value_name = "N", default_value = "2")]
+    min_members: usize,
+
+    /// Print a diff of the changes to be made without making them.
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+/// A manifest of the workspace, along with its contents before any changes.
+struct WorkspaceManifest {
+    /// Canonical path to the manifest
+    path: PathBuf,
+    original: String,
+    manifest: Manifest,
+}
+
+impl WorkspaceManifest {
+    fn open(path: &Path) -> Result<Self> {
+        let path = fs::canonicalize(path)?;
+        let original = fs::read_to_string(&path)?;
+        let manifest = original.parse::<Manifest>()?;
+        Ok(WorkspaceManifest {
+            path,
+            original,
+            manifest,
+        })
+    }
+}
+
+/// The registry dependencies that share a key and a registry, across the workspace.
+#[derive(Default)]
+struct SharedDependency {
+    /// The package the key refers to in the first declaration
+    name: String,
+    registry: Option<String>,
+    /// Indices of the declaring manifests, with the table and the declaration
+    declarations: Vec<(usize, Vec<String>, Dependency)>,
+}
+
+impl SharedDependency {
+    /// Count the members declaring the dependency, no matter in how many tables.
+    fn member_count(&self) -> usize {
+        let mut members = self
+            .declarations
+            .iter()
+            .map(|&(member, _, _)| member)
+            .collect::<Vec<_>>();
+        members.dedup();
+        members.len()
+    }
+}
+
+/// Split a requirement like `1.2`, `~0.3.1` or `>=1.0.0` into its operator and its version, with
+/// requirements without an operator being caret requirements.
+///
+/// Returns `None` for requirements with several bounds, and for `>`, `<` and `<=` requirements,
+/// whose lowest allowed version cannot be told from the requirement alone.
+fn split_operator(requirement: &str) -> Option<(&str, &str)> {
+    let requirement = requirement.trim();
+    if requirement.contains(',') {
+        return None;
+    }
+    for operator in &[">=", "=", "^", "~"] {
+        if let Some(version) = requirement.strip_prefix(operator) {
+            return Some((operator, version.trim()));
+        }
+    }
+    if requirement.starts_with(&['<', '>'][..]) {
+        return None;
+    }
+    Some(("^", requirement))
+}
+
+/// Get the lowest version a requirement like `1.2`, `~0.3.1` or `>=1.0.0` allows.
+///
+/// Returns `None` for wildcards and the requirements `split_operator` rejects.
+fn lower_bound(requirement: &str) -> Option<semver::Version> {
+    let (_, version) = split_operator(requirement)?;
+    let release = version.split(&['-', '+'][..]).next()?;
+    let missing = match release.matches('.').count() {
+        0 => ".0.0",
+        1 => ".0",
+        _ => "",
+    };
+    semver::Version::parse(&version.replacen(release, &format!("{}{}", release, missing), 1)).ok()
+}
+
+/// Get the part of a version that must match for versions to satisfy the same requirements with
+/// `operator`, e.g. the major version for `^1.2` and `^1.5`.
+fn compatibility(operator: &str, version: &semver::Version) -> (u64, u64, u64) {
+    match (operator, version.major, version.minor) {
+        ("=", _, _) => (version.major, version.minor, version.patch),
+        ("~", major, minor) => (major, minor, 0),
+        (_, 0, 0) => (0, 0, version.patch),
+        (_, 0, minor) => (0, minor, 0),
+        (_, major, _) => (major, 0, 0),
+    }
+}
+
+/// Pick the requirement all of `requirements` can be replaced with: the one with the highest
+/// lower bound. Fails with the reason if they use different operators, as picking one of them
+/// would change what the others allow, or if they are not compatible.
+fn unify(requirements: &[String]) -> ::std::result::Result<String, String> {
+    let mut bounds = Vec::new();
+    for requirement in requirements {
+        match (split_operator(requirement), lower_bound(requirement)) {
+            (Some((operator, _)), Some(bound)) => bounds.push((operator, bound, requirement)),
+            _ => return Err(format!("cannot unify requirement `{}`", requirement)),
+        }
+    }
+
+    let mut requirements = requirements.to_vec();
+    requirements.dedup();
+    let (operator, highest, requirement) = bounds
+        .iter()
+        .max_by(|a, b| a.1.cmp(&b.1))
+        .ok_or_else(|| "no requirements".to_owned())?;
+    if bounds.iter().any(|(other, _, _)| other != operator) {
+        return Err(format!(
+            "requirements with different operators {}",
+            requirements.join(", ")
+        ));
+    }
+    if bounds
+        .iter()
+        .any(|(_, bound, _)| compatibility(operator, bound) != compatibility(operator, highest))
+    {
+        return Err(format!(
+            "incompatible requirements {}",
+            requirements.join(", ")
+        ));
+    }
+    Ok((*requirement).clone())
+}
+
+/// The number of unchanged lines shown around the changes of a diff.
+const CONTEXT: usize = 3;
+
+/// Get a unified diff of the changes between `old` and `new`, without the file headers.
+fn diff(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // Longest common subsequence of lines, computed from the end.
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // Every line of both, marked as kept, removed or added, with the numbers of the old and new
+    // lines before it.
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i], i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', old[i], i, j));
+            i += 1;
+        } else {
+            lines.push(('+', new[j], i, j));
+            j += 1;
+        }
+    }
+
+    let changes = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.0 != ' ')
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let mut output = String::new();
+    let mut next = 0;
+    while next < changes.len() {
+        // Extend the hunk while the context of the next change overlaps it.
+        let start = changes[next].saturating_sub(CONTEXT);
+        let mut end = (changes[next] + CONTEXT + 1).min(lines.len());
+        next += 1;
+        while next < changes.len() && changes[next] <= end + CONTEXT {
+            end = (changes[next] + CONTEXT + 1).min(lines.len());
+            next += 1;
+        }
+
+        let hunk = &lines[start..end];
+        let old_len = hunk.iter().filter(|line| line.0 != '+').count();
+        let new_len = hunk.iter().filter(|line| line.0 != '-').count();
+        // Empty ranges start at the line before them.
+        let old_start = hunk[0].2 + if old_len == 0 { 0 } else { 1 };
+        let new_start = hunk[0].3 + if new_len == 0 { 0 } else { 1 };
+        output.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_len, new_start, new_len
+        ));
+        for (marker, line, _, _) in hunk {
+            output.push_str(&format!("{}{}\n", marker, line));
+        }
+    }
+    output
+}
+
+/// Print a unified diff of the changes between `old` and `new`.
+fn print_diff(path: &Path, old: &str, new: &str) {
+    println!("--- {}", path.display());
+    println!("+++ {}", path.display());
+    print!("{}", diff(old, new));
+}
+
+fn print_msg(action: &str, color: Color, message: &str) -> Result<()> {
+    let colorchoice = if atty::is(atty::Stream::Stdout) {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+    let mut output = StandardStream::stdout(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+    write!(output, "{:>12}", action)?;
+    output.reset()?;
+    writeln!(output, " {}", message)?;
+    Ok(())
+}
+
+/// Load the root manifest and the manifests of all members of the workspace. Returns them along
+/// with the index of the root manifest, which is also a member unless it is virtual.
+fn load_workspace(manifest_path: &Option<PathBuf>) -> Result<(Vec<WorkspaceManifest>, usize)> {
+    let mut cmd = cargo_metadata::MetadataCommand::new();
+    cmd.no_deps();
+    if let Some(path) = manifest_path {
+        cmd.manifest_path(path);
+    }
+    let result = cmd
+        .exec()
+        .map_err(|e| Error::from(e.compat()).chain_err(|| "Failed to get workspace metadata"))?;
+
+    let mut manifests = result
+        .packages
+        .iter()
+        .map(|package| WorkspaceManifest::open(&package.manifest_path))
+        .collect::<Result<Vec<_>>>()?;
+    let root_path = fs::canonicalize(result.workspace_root.join("Cargo.toml"))?;
+    let root = match manifests.iter().position(|m| m.path == root_path) {
+        Some(root) => root,
+        None => {
+            manifests.push(WorkspaceManifest::open(&root_path)?);
+            manifests.len() - 1
+        }
+    };
+    Ok((manifests, root))
+}
+
+/// Collect the registry dependencies of all manifests, keyed by their key in the manifest and
+/// their registry.
+fn get_shared_dependencies(
+    manifests: &[WorkspaceManifest],
+) -> BTreeMap<(String, Option<String>), SharedDependency> {
+    let mut shared = BTreeMap::<_, SharedDependency>::new();
+    for (index, manifest) in manifests.iter().enumerate() {
+        for declaration in manifest.manifest.get_dependencies().filter_map(|d| d.ok()) {
+            let dependency = &declaration.dependency;
+            if dependency.inherited()
+                || dependency.path().is_some()
+                || dependency.git().is_some()
+                || dependency.version().is_none()
+            {
+                continue;
+            }
+            let entry = shared
+                .entry((
+                    declaration.key.clone(),
+                    dependency.registry().map(String::from),
+                ))
+                .or_default();
+            if entry.declarations.is_empty() {
+                entry.name = dependency.name.clone();
+            }
+            entry.registry = dependency.registry().map(String::from);
+            entry
+                .declarations
+                .push((index, declaration.table_path(), declaration.dependency));
+        }
+    }
+    shared
+}
+
+/// Hoist a dependency into the workspace root and make its declarations inherit it.
+fn consolidate(
+    manifests: &mut [WorkspaceManifest],
+    root: usize,
+    key: &str,
+    shared: &SharedDependency,
+) -> Result<::std::result::Result<String, String>> {
+    let workspace_table = vec!["workspace".to_owned(), "dependencies".to_owned()];
+
+    // Members may use the same key for different packages, e.g. with `package = "serde_json"`.
+    let mut packages = shared
+        .declarations
+        .iter()
+        .map(|(_, _, dep)| dep.name.as_str())
+        .collect::<Vec<_>>();
+    packages.sort();
+    packages.dedup();
+    if packages.len() > 1 {
+        return Ok(Err(format!(
+            "members use the key for different packages {}",
+            packages.join(", ")
+        )));
+    }
+
+    let mut requirements = shared
+        .declarations
+        .iter()
+        .filter_map(|(_, _, dep)| dep.version().map(String::from))
+        .collect::<Vec<_>>();
+    // An entry already in the workspace root has to be compatible as well.
+    let existing = &manifests[root].manifest.data["workspace"]["dependencies"][key];
+    let existing = if existing.is_none() {
+        None
+    } else {
+        match Dependency::from_toml(key, existing) {
+            Ok(existing) => Some(existing),
+            Err(_) => return Ok(Err("invalid entry in `[workspace.dependencies]`".to_owned())),
+        }
+    };
+    if let Some(ref existing) = existing {
+        requirements.extend(existing.version().map(String::from));
+    }
+    let requirement = match unify(&requirements) {
+        Ok(requirement) => requirement,
+        Err(reason) => return Ok(Err(reason)),
+    };
+
+    let default_features = shared.declarations[0].2.default_features();
+    if shared
+        .declarations
+        .iter()
+        .any(|(_, _, dep)| dep.default_features() != default_features)
+    {
+        return Ok(Err("members disagree on `default-features`".to_owned()));
+    }
+    if let Some(ref existing) = existing {
+        if existing.name != shared.name
+            || existing.git().is_some()
+            || existing.registry() != shared.registry.as_deref()
+            || existing.default_features() != default_features
+        {
+            return Ok(Err(
+                "members disagree with the entry in `[workspace.dependencies]`".to_owned(),
+            ));
+        }
+    }
+
+    if existing.is_some() {
+        // Only the requirement changes, keys like `path` stay as they are.
+        let entry = &mut manifests[root].manifest.get_table(&workspace_table)?[key];
+        if entry.is_str() {
+            *entry = toml_edit::value(requirement.as_str());
+        } else {
+            entry["version"] = toml_edit::value(requirement.as_str());
+            if let Some(table) = entry.as_inline_table_mut() {
+                table.fmt();
+            }
+        }
+    } else {
+        let mut root_dep = Dependency::new(&shared.name)
+            .set_version(&requirement)
+            .set_default_features(default_features);
+        if let Some(ref registry) = shared.registry {
+            root_dep = root_dep.set_registry(registry);
+        }
+        if key != shared.name {
+            root_dep = root_dep.set_rename(key);
+        }
+        manifests[root]
+            .manifest
+            .insert_into_table(&workspace_table, &root_dep)?;
+    }
+
+    for (member, table_path, dep) in &shared.declarations {
+        manifests[*member].manifest.update_table_named_entry(
+            table_path,
+            key,
+            &dep.clone().set_inherited(),
+            false,
+        )?;
+    }
+
+    Ok(Ok(requirement))
+}
+
+fn handle_consolidate(args: &Args) -> Result<()> {
+    let (mut manifests, root) = load_workspace(&args.manifest_path)?;
+
+    for ((key, _), shared) in get_shared_dependencies(&manifests) {
+        let members = shared.member_count();
+        if members < args.min_members {
+            continue;
+        }
+        match consolidate(&mut manifests, root, &key, &shared)? {
+            Ok(requirement) => print_msg(
+                "Consolidating",
+                Color::Green,
+                &format!("{} v{} ({} members)", key, requirement, members),
+            )?,
+            Err(reason) => print_msg("Skipping", Color::Yellow, &format!("{}: {}", key, reason))?,
+        }
+    }
+
+    for manifest in &manifests {
+        let new = manifest.manifest.data.to_string_in_original_order();
+        if new == manifest.original {
+            continue;
+        }
+        if args.dry_run {
+            print_diff(&manifest.path, &manifest.original, &new);
+        } else {
+            let mut file = Manifest::find_file(&Some(manifest.path.clone()))?;
+            manifest.manifest.write_to_file(&mut file)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args: Command = Command::from_args();
+    let Command::Consolidate(args) = args;
+
+    if let Err(err) = handle_consolidate(&args) {
+        eprintln!("Command failed due to unhandled error: {}\n", err);
+
+        for e in err.iter().skip(1) {
+            eprintln!("Caused by: {}", e);
+        }
+
+        if let Some(backtrace) = err.backtrace() {
+            eprintln!("Backtrace: {:?}", backtrace);
+        }
+
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower_bounds() {
+        let version = |v| semver::Version::parse(v).unwrap();
+        assert_eq!(lower_bound("0.2"), Some(version("0.2.0")));
+        assert_eq!(lower_bound("^1"), Some(version("1.0.0")));
+        assert_eq!(lower_bound("~0.3.1"), Some(version("0.3.1")));
+        assert_eq!(lower_bound(">= 1.2.3-alpha"), Some(version("1.2.3-alpha")));
+        assert_eq!(lower_bound("*"), None);
+        assert_eq!(lower_bound(">=1.0, <2.0"), None);
+        assert_eq!(lower_bound(">1.0"), None);
+        assert_eq!(lower_bound("<2.0"), None);
+        assert_eq!(lower_bound("<= 2.0"), None);
+    }
+
+    #[test]
+    fn diff_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+        assert_eq!(
+            diff(old, new),
+            "@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -10,3 +10,4 @@\n j\n k\n l\n+m\n"
+        );
+
+        assert_eq!(diff("", "a\n"), "@@ -0,0 +1,1 @@\n+a\n");
+        assert_eq!(diff("a\n", "a\n"), "");
+    }
+
+    #[test]
+    fn unify_compatible_requirements() {
+        let requirements = vec!["0.2.28".to_owned(), "0.2".to_owned(), "0.2.62".to_owned()];
+        assert_eq!(unify(&requirements), Ok("0.2.62".to_owned()));
+
+        let requirements = vec!["^1.0".to_owned(), "1.4".to_owned()];
+        assert_eq!(unify(&requirements), Ok("1.4".to_owned()));
+
+        let requirements = vec!["~1.4".to_owned(), "~1.4.2".to_owned()];
+        assert_eq!(unify(&requirements), Ok("~1.4.2".to_owned()));
+    }
+
+    #[test]
+    fn unify_mixed_operators() {
+        let requirements = vec!["1.0".to_owned(), "~1.4".to_owned()];
+        assert!(unify(&requirements).is_err());
+
+        let requirements = vec![">=1.0".to_owned(), "1.4".to_owned()];
+        assert!(unify(&requirements).is_err());
+    }
+
+    #[test]
+    fn unify_incompatible_requirements() {
+        let requirements = vec!["0.3".to_owned(), "0.2".to_owned()];
+        assert!(unify(&requirements).is_err());
+
+        let requirements = vec!["1.0".to_owned(), "*".to_owned()];
+        assert!(unify(&requirements).is_err());
+
+        let requirements = vec!["~1.3".to_owned(), "~1.4".to_owned()];
+        assert!(unify(&requirements).is_err());
+
+        let requirements = vec!["=1.4.0".to_owned(), "=1.4.1".to_owned()];
+        assert!(unify(&requirements).is_err());
+    }
+}
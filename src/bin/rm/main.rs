@@ -14,7 +14,7 @@
 #[macro_use]
 extern crate error_chain;
 
-use cargo_edit::Manifest;
+use cargo_edit::{FeatureCleanup, Manifest};
 use std::io::Write;
 use std::path::PathBuf;
 use std::process;
@@ -55,6 +55,19 @@ struct Args {
     #[structopt(long = "build", short = "B", conflicts_with = "dev")]
     build: bool,
 
+    /// Remove as dependency from the given target platform.
+    #[structopt(long = "target", value_name = "target")]
+    target: Option<String>,
+
+    /// Remove crate from every dependency section it appears in, including target-specific ones.
+    #[structopt(
+        long = "all-sections",
+        conflicts_with = "dev",
+        conflicts_with = "build",
+        conflicts_with = "target"
+    )]
+    all_sections: bool,
+
     /// Path to the manifest to remove a dependency from.
     #[structopt(long = "manifest-path", value_name = "path")]
     manifest_path: Option<PathBuf>,
@@ -66,18 +79,31 @@ struct Args {
 
 impl Args {
     /// Get depenency section
-    pub fn get_section(&self) -> &'static str {
-        if self.dev {
+    pub fn get_section(&self) -> Result<Vec<String>> {
+        let section = if self.dev {
             "dev-dependencies"
         } else if self.build {
             "build-dependencies"
         } else {
             "dependencies"
+        };
+
+        if let Some(ref target) = self.target {
+            if target.is_empty() {
+                bail!("Target specification may not be empty");
+            }
+            Ok(vec![
+                "target".to_owned(),
+                target.clone(),
+                section.to_owned(),
+            ])
+        } else {
+            Ok(vec![section.to_owned()])
         }
     }
 }
 
-fn print_msg(name: &str, section: &str) -> Result<()> {
+fn print_msg(name: &str, section: &[String]) -> Result<()> {
     let colorchoice = if atty::is(atty::Stream::Stdout) {
         ColorChoice::Auto
     } else {
@@ -87,10 +113,34 @@ fn print_msg(name: &str, section: &str) -> Result<()> {
     output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
     write!(output, "{:>12}", "Removing")?;
     output.reset()?;
+    let section = if section.len() == 1 {
+        section[0].clone()
+    } else {
+        format!("{} for target `{}`", &section[2], &section[1])
+    };
     writeln!(output, " {} from {}", name, section)?;
     Ok(())
 }
 
+fn print_feature_msg(cleanup: &FeatureCleanup) -> Result<()> {
+    let colorchoice = if atty::is(atty::Stream::Stdout) {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+    let mut output = StandardStream::stdout(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+    write!(output, "{:>12}", "Removing")?;
+    output.reset()?;
+    match cleanup {
+        FeatureCleanup::RemovedMember { feature, member } => {
+            writeln!(output, " {} from feature {}", member, feature)?
+        }
+        FeatureCleanup::RemovedFeature(feature) => writeln!(output, " feature {}", feature)?,
+    }
+    Ok(())
+}
+
 fn handle_rm(args: &Args) -> Result<()> {
     let manifest_path = &args.manifest_path;
     let mut manifest = Manifest::open(manifest_path)?;
@@ -98,12 +148,28 @@ fn handle_rm(args: &Args) -> Result<()> {
 
     deps.iter()
         .map(|dep| {
-            if !args.quiet {
-                print_msg(&dep, args.get_section())?;
+            if args.all_sections {
+                let sections = manifest.remove_from_all_tables(dep)?;
+                if !args.quiet {
+                    for section in sections {
+                        print_msg(&dep, &section)?;
+                    }
+                }
+            } else {
+                let section = args.get_section()?;
+                if !args.quiet {
+                    print_msg(&dep, &section)?;
+                }
+                manifest.remove_from_table(&section, dep)?;
+            }
+
+            // Features referring to the removed dependency would stop the manifest from building.
+            for cleanup in manifest.remove_feature_references(dep) {
+                if !args.quiet {
+                    print_feature_msg(&cleanup)?;
+                }
             }
-            manifest
-                .remove_from_table(args.get_section(), dep)
-                .map_err(Into::into)
+            Ok(())
         })
         .collect::<Result<Vec<_>>>()
         .map_err(|err| {
@@ -16,11 +16,12 @@ extern crate error_chain;
 
 use crate::errors::*;
 use cargo_edit::{
-    find, get_latest_dependency, registry_url, update_registry_index, CrateName, Dependency,
-    LocalManifest,
+    find, find_workspace_root, get_compatible_dependency, get_latest_dependency, registry_index,
+    CargoConfig, CrateName, Dependency, LocalManifest, RegistryIndex, VersionUpgrade,
 };
 use failure::Fail;
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process;
@@ -51,12 +52,38 @@ local lock file (Cargo.lock).
 If `<dependency>`(s) are provided, only the specified dependencies will be upgraded. The version to
 upgrade to for each can be specified with e.g. `docopt@0.8.0` or `serde@>=0.9,<2.0`.
 
+Requirements upgraded to the latest versions keep their operator and precision, e.g. `~1.2` becomes
+`~1.4` and `1` becomes `2`. Ranges such as `>=1.0, <3` keep their lower bound and get a new upper
+bound, e.g. `<4`.
+
+Dependencies pinned to an exact version, e.g. `=1.2.3`, are reported and left alone, unless the
+'--pinned' flag is supplied or a version to upgrade to is given for them.
+
+The crates given with '--exclude' are never upgraded. Neither are the ones listed in `ignore` of
+`[package.metadata.cargo-edit]` or `[workspace.metadata.cargo-edit]`, e.g. `ignore = [\"openssl\"]`,
+unless they are given as `<dependency>`. A dependency skipped in any package is skipped in all of
+them, and each skipped dependency is reported with the reason.
+
 Dev, build, and all target dependencies will also be upgraded. Only dependencies from crates.io are
-supported. Git/path dependencies will be ignored.
+supported. Git/path dependencies will be ignored. Dependencies inherited with `workspace = true`
+are upgraded once, in `[workspace.dependencies]` of the workspace root.
 
 All packages in the workspace will be upgraded if the `--all` flag is supplied. The `--all` flag may
 be supplied in the presence of a virtual manifest.
 
+If the '--compatible' flag is supplied, dependencies are only upgraded to the latest version still
+matched by their current requirement, e.g. from `1.2` to `1.9.3`. The dependencies with newer,
+incompatible releases are reported and left alone. The '--incompatible' flag does the opposite, only
+upgrading dependencies to releases not matched by their current requirement, e.g. from `0.8` to
+`1.1.0`.
+
+Each upgrade is classified as major, minor or patch, after the leftmost version component it
+changes. As for cargo, the first non-zero component counts as the major one, so `0.2` to `0.3` is a
+major upgrade. With '--message-format json', the upgrades are printed as JSON objects instead, one per
+line, with the `manifest`, the `crate`, the requirement it is upgraded `from` and `to`, and its
+`kind`. The dependencies not upgraded are printed with their requirement and the reason they were
+`skipped`, or the `incompatible` release they were not upgraded to.
+
 If the '--to-lockfile' flag is supplied, all dependencies will be upgraded to the currently locked
 version as recorded in the Cargo.lock file. This flag requires that the Cargo.lock file is
 up-to-date. If the lock file is missing, or it needs to be updated, cargo-upgrade will exit with an
@@ -86,13 +113,56 @@ struct Args {
     #[structopt(long = "dry-run")]
     dry_run: bool,
 
-    /// Run without accessing the network
+    /// Only upgrade to versions matched by the current version requirements, e.g. `1.2` to
+    /// `1.9.3`, and report the dependencies with incompatible releases.
+    #[structopt(long = "compatible", conflicts_with = "to-lockfile")]
+    compatible: bool,
+
+    /// Only upgrade to versions not matched by the current version requirements, e.g. `0.8` to
+    /// `1.1.0`, leaving the dependencies with compatible upgrades alone.
+    #[structopt(
+        long = "incompatible",
+        conflicts_with = "compatible",
+        conflicts_with = "to-lockfile"
+    )]
+    incompatible: bool,
+
+    /// Also upgrade dependencies pinned to an exact version with `=`, which are skipped otherwise.
+    #[structopt(long = "pinned")]
+    pinned: bool,
+
+    /// Crates not to upgrade, in addition to the ones ignored in `package.metadata.cargo-edit` or
+    /// `workspace.metadata.cargo-edit`.
+    #[structopt(long = "exclude", value_name = "crate", number_of_values = 1)]
+    exclude: Vec<String>,
+
+    /// Print the upgrades for humans, or as JSON objects, one per line.
+    #[structopt(
+        long = "message-format",
+        value_name = "fmt",
+        possible_value = "human",
+        possible_value = "json",
+        default_value = "human"
+    )]
+    message_format: String,
+
+    /// Run without accessing the network. Also set with `net.offline` or `CARGO_NET_OFFLINE`.
     #[structopt(long = "offline")]
     pub offline: bool,
 
+    /// Do not fetch registry indices that have been fetched before. By default, an index is
+    /// fetched again once it is older than `CARGO_EDIT_INDEX_FRESHNESS` seconds (60 if unset).
+    #[structopt(long = "no-update")]
+    pub no_update: bool,
+
     /// Upgrade all packages to the version in the lockfile.
     #[structopt(long = "to-lockfile", conflicts_with = "dependency")]
     pub to_lockfile: bool,
+
+    /// Override a cargo config value, given as `KEY=VALUE` in TOML syntax or as the path to an
+    /// additional config file.
+    #[structopt(long = "config", value_name = "config", number_of_values = 1)]
+    pub config: Vec<String>,
 }
 
 /// A collection of manifests.
@@ -108,6 +178,46 @@ fn is_version_dep(dependency: &cargo_metadata::Dependency) -> bool {
     }
 }
 
+/// Check whether a dependency is pinned to an exact version with `=`.
+fn is_pinned(req: &semver::VersionReq) -> bool {
+    let req = req.to_string();
+    req.starts_with('=') && !req.contains(',')
+}
+
+/// Get the crates listed in `ignore` of the `metadata.cargo-edit` table of a manifest's `package` or
+/// `workspace` table.
+fn ignore_list(manifest: &LocalManifest, table: &str) -> Vec<String> {
+    manifest.data[table]["metadata"]["cargo-edit"]["ignore"]
+        .as_array()
+        .map(|ignore| {
+            ignore
+                .iter()
+                .filter_map(|name| name.as_str())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Get the names of the dependencies a package inherits from `[workspace.dependencies]`.
+fn inherited_dependencies(
+    manifest: &LocalManifest,
+    package: &cargo_metadata::Package,
+) -> HashSet<String> {
+    let keys = manifest
+        .get_dependencies()
+        .filter_map(|dep| dep.ok())
+        .filter(|dep| dep.dependency.inherited())
+        .map(|dep| dep.key)
+        .collect::<HashSet<_>>();
+    package
+        .dependencies
+        .iter()
+        .filter(|dep| keys.contains(dep.rename.as_ref().unwrap_or(&dep.name)))
+        .map(|dep| dep.name.clone())
+        .collect()
+}
+
 fn dry_run_message() -> Result<()> {
     let bufwtr = BufferWriter::stdout(ColorChoice::Always);
     let mut buffer = bufwtr.buffer();
@@ -125,6 +235,26 @@ fn dry_run_message() -> Result<()> {
         .chain_err(|| "Failed to print dry run message")
 }
 
+/// Print the upgrades made to a manifest, as JSON objects tagged with the manifest's path if
+/// `json` is set.
+fn report_upgrades(
+    manifest: &LocalManifest,
+    upgrades: &[VersionUpgrade],
+    json: bool,
+) -> Result<()> {
+    for upgrade in upgrades {
+        if !json {
+            upgrade.print()?;
+            continue;
+        }
+        let mut message =
+            serde_json::to_value(upgrade).chain_err(|| "Failed to serialize upgrade")?;
+        message["manifest"] = manifest.path.display().to_string().into();
+        println!("{}", message);
+    }
+    Ok(())
+}
+
 impl Manifests {
     /// Get all manifests in the workspace.
     fn get_all(manifest_path: &Option<PathBuf>) -> Result<Self> {
@@ -178,9 +308,44 @@ impl Manifests {
         Ok(Manifests(vec![(manifest, package.to_owned())]))
     }
 
+    /// Get the crates ignored in `[package.metadata.cargo-edit]` of any of the manifests, or in
+    /// `[workspace.metadata.cargo-edit]` of their workspace root, with the reason to skip them.
+    fn get_ignored(&self) -> Result<HashMap<String, String>> {
+        let mut ignored = HashMap::new();
+        let root_path = match self.0.first() {
+            Some((manifest, _)) => find_workspace_root(&manifest.path).ok(),
+            None => None,
+        };
+        if let Some(root_path) = root_path {
+            let root = LocalManifest::try_new(&root_path)?;
+            for name in ignore_list(&root, "workspace") {
+                ignored.insert(
+                    name,
+                    "ignored in `workspace.metadata.cargo-edit`".to_owned(),
+                );
+            }
+        }
+        for (manifest, _) in &self.0 {
+            for name in ignore_list(manifest, "package") {
+                ignored.insert(name, "ignored in `package.metadata.cargo-edit`".to_owned());
+            }
+        }
+        Ok(ignored)
+    }
+
     /// Get the the combined set of dependencies to upgrade. If the user has specified
     /// per-dependency desired versions, extract those here.
-    fn get_dependencies(&self, only_update: Vec<String>) -> Result<DesiredUpgrades> {
+    ///
+    /// The crates in `exclude` are skipped. So are the `ignored` ones, unless the user asked for
+    /// them, and the dependencies pinned with `=`, unless `pinned` is set or the user specified a
+    /// version for them. A dependency skipped in any of the manifests is skipped in all of them.
+    fn get_dependencies(
+        &self,
+        only_update: Vec<String>,
+        pinned: bool,
+        exclude: &[String],
+        ignored: &HashMap<String, String>,
+    ) -> Result<(DesiredUpgrades, SkippedUpgrades)> {
         // Map the names of user-specified dependencies to the (optionally) requested version.
         let selected_dependencies = only_update
             .into_iter()
@@ -196,11 +361,48 @@ impl Manifests {
             })
             .collect::<Result<HashMap<_, _>>>()?;
 
-        Ok(DesiredUpgrades(
+        let skip_reason = |dependency: &cargo_metadata::Dependency| {
+            let selected = selected_dependencies.get(&dependency.name);
+            if selected.is_none() && !selected_dependencies.is_empty() {
+                // The user didn't ask for this dependency, so there is nothing to skip.
+                return None;
+            }
+            if exclude.contains(&dependency.name) {
+                Some("excluded".to_owned())
+            } else if let (None, Some(reason)) = (selected, ignored.get(&dependency.name)) {
+                Some(reason.clone())
+            } else if !pinned
+                && selected.map_or(true, Option::is_none)
+                && is_pinned(&dependency.req)
+            {
+                Some("pinned".to_owned())
+            } else {
+                None
+            }
+        };
+        let mut skipped = self
+            .0
+            .iter()
+            .flat_map(|(_, package)| &package.dependencies)
+            .filter(|dependency| is_version_dep(dependency))
+            .filter_map(|dependency| {
+                skip_reason(dependency)
+                    .map(|reason| (dependency.name.clone(), dependency.req.to_string(), reason))
+            })
+            .collect::<Vec<_>>();
+        skipped.sort();
+        skipped.dedup();
+        let skipped_names = skipped
+            .iter()
+            .map(|(name, _, _)| name.clone())
+            .collect::<HashSet<_>>();
+
+        let upgrades = DesiredUpgrades(
             self.0
                 .iter()
                 .flat_map(|&(_, ref package)| package.dependencies.clone())
                 .filter(is_version_dep)
+                .filter(|dependency| !skipped_names.contains(&dependency.name))
                 .filter_map(|dependency| {
                     let is_prerelease = dependency.req.to_string().contains('-');
                     if selected_dependencies.is_empty() {
@@ -211,7 +413,7 @@ impl Manifests {
                             dep = dep.set_rename(&rename);
                         }
                         Some((
-                            dep,
+                            (dep, dependency.req),
                             UpgradeMetadata {
                                 registry: dependency.registry,
                                 version: None,
@@ -223,7 +425,7 @@ impl Manifests {
                         // was specified, populating the registry from the lockfile metadata.
                         match selected_dependencies.get(&dependency.name) {
                             Some(version) => Some((
-                                Dependency::new(&dependency.name),
+                                (Dependency::new(&dependency.name), dependency.req),
                                 UpgradeMetadata {
                                     registry: dependency.registry,
                                     version: version.clone(),
@@ -235,24 +437,107 @@ impl Manifests {
                     }
                 })
                 .collect(),
-        ))
+        );
+        Ok((upgrades, SkippedUpgrades(skipped)))
     }
 
     /// Upgrade the manifests on disk following the previously-determined upgrade schema.
-    fn upgrade(self, upgraded_deps: &ActualUpgrades, dry_run: bool) -> Result<()> {
-        if dry_run {
+    ///
+    /// Dependencies inherited from the workspace are upgraded once, in the workspace root. The
+    /// upgrades are reported as JSON if `json` is set.
+    fn upgrade(self, upgraded_deps: &ActualUpgrades, dry_run: bool, json: bool) -> Result<()> {
+        if dry_run && !json {
             dry_run_message()?;
         }
 
-        for (mut manifest, package) in self.0 {
-            println!("{}:", package.name);
-
-            for (dep, version) in &upgraded_deps.0 {
+        let new_dependencies = upgraded_deps
+            .0
+            .iter()
+            .map(|((dep, req), (version, preserve_requirement))| {
                 let mut new_dep = Dependency::new(&dep.name).set_version(version);
                 if let Some(rename) = dep.rename() {
                     new_dep = new_dep.set_rename(&rename);
                 }
-                manifest.upgrade(&new_dep, dry_run)?;
+                (new_dep, req, *preserve_requirement)
+            })
+            .collect::<Vec<_>>();
+
+        // The new versions may have been chosen to match the current requirements, so each
+        // entry is only upgraded to the version found for its own requirement.
+        self.upgrade_each(json, |manifest, package, inherited, is_root| {
+            let mut upgrades = Vec::new();
+            if package.is_some() {
+                for (new_dep, req, preserve_requirement) in &new_dependencies {
+                    upgrades.extend(manifest.upgrade(
+                        new_dep,
+                        Some(req),
+                        *preserve_requirement,
+                        dry_run,
+                    )?);
+                }
+            }
+            if is_root {
+                for (new_dep, req, preserve_requirement) in new_dependencies
+                    .iter()
+                    .filter(|(dep, _, _)| inherited.contains(&dep.name))
+                {
+                    upgrades.extend(manifest.upgrade_workspace_dependency(
+                        new_dep,
+                        Some(req),
+                        *preserve_requirement,
+                        dry_run,
+                    )?);
+                }
+            }
+            report_upgrades(manifest, &upgrades, json)
+        })
+    }
+
+    /// Call `upgrade` on every manifest, and on the workspace root if any of the packages inherit
+    /// dependencies from it, so the root is visited exactly once.
+    ///
+    /// `upgrade` gets the manifest, its package (`None` for a virtual root, or a root outside of
+    /// the selected packages), the names of all inherited dependencies and whether the manifest is
+    /// the workspace root. Each manifest is announced before its upgrades, unless they are reported
+    /// as JSON.
+    fn upgrade_each<F>(self, json: bool, mut upgrade: F) -> Result<()>
+    where
+        F: FnMut(
+            &mut LocalManifest,
+            Option<&cargo_metadata::Package>,
+            &HashSet<String>,
+            bool,
+        ) -> Result<()>,
+    {
+        let inherited = self
+            .0
+            .iter()
+            .flat_map(|(manifest, package)| inherited_dependencies(manifest, package))
+            .collect::<HashSet<_>>();
+        let root_path = match self.0.first() {
+            Some((manifest, _)) if !inherited.is_empty() => {
+                Some(find_workspace_root(&manifest.path)?)
+            }
+            _ => None,
+        };
+
+        let mut visited_root = false;
+        for (mut manifest, package) in self.0 {
+            let is_root = root_path.is_some() && fs::canonicalize(&manifest.path).ok() == root_path;
+            visited_root |= is_root;
+            if !json {
+                println!("{}:", package.name);
+            }
+            upgrade(&mut manifest, Some(&package), &inherited, is_root)?;
+        }
+
+        if let Some(root_path) = root_path {
+            if !visited_root {
+                if !json {
+                    println!("workspace:");
+                }
+                let mut root = LocalManifest::try_new(&root_path)?;
+                upgrade(&mut root, None, &inherited, true)?;
             }
         }
 
@@ -260,8 +545,18 @@ impl Manifests {
     }
 
     /// Update dependencies in Cargo.toml file(s) to match the corresponding
-    /// version in Cargo.lock.
-    fn sync_to_lockfile(self, dry_run: bool) -> Result<()> {
+    /// version in Cargo.lock. Cargo does not access the network when `offline`. The upgrades are
+    /// reported as JSON if `json` is set.
+    ///
+    /// The crates in `exclude` or `ignored` are skipped.
+    fn sync_to_lockfile(
+        self,
+        dry_run: bool,
+        offline: bool,
+        json: bool,
+        exclude: &[String],
+        ignored: &HashMap<String, String>,
+    ) -> Result<()> {
         // Get locked dependencies. For workspaces with multiple Cargo.toml
         // files, there is only a single lockfile, so it suffices to get
         // metadata for any one of Cargo.toml files.
@@ -271,7 +566,11 @@ impl Manifests {
             })?;
         let mut cmd = cargo_metadata::MetadataCommand::new();
         cmd.manifest_path(manifest.path.clone());
-        cmd.other_options(vec!["--locked".to_string()]);
+        let mut options = vec!["--locked".to_string()];
+        if offline {
+            options.push("--offline".to_string());
+        }
+        cmd.other_options(options);
 
         let result = cmd
             .exec()
@@ -283,34 +582,85 @@ impl Manifests {
             .filter(|p| p.source.is_some()) // Source is none for local packages
             .collect::<Vec<_>>();
 
-        if dry_run {
-            dry_run_message()?;
-        }
-
-        for (mut manifest, package) in self.0 {
-            println!("{}:", package.name);
+        let skip_reason = |name: &String| {
+            if exclude.contains(name) {
+                Some("excluded".to_owned())
+            } else {
+                ignored.get(name).cloned()
+            }
+        };
+        let mut skipped = self
+            .0
+            .iter()
+            .flat_map(|(_, package)| &package.dependencies)
+            .filter(|d| is_version_dep(d))
+            .filter_map(|d| {
+                skip_reason(&d.name).map(|reason| (d.name.clone(), d.req.to_string(), reason))
+            })
+            .collect::<Vec<_>>();
+        skipped.sort();
+        skipped.dedup();
 
-            // Upgrade the manifests one at a time, as multiple manifests may
-            // request the same dependency at differing versions.
-            for (name, version) in package
+        // The requested dependency may be present in the lock file with different versions,
+        // but only one will be semver-compatible with the requested version.
+        let locked_versions = |package: &cargo_metadata::Package| {
+            package
                 .dependencies
-                .clone()
-                .into_iter()
-                .filter(is_version_dep)
+                .iter()
+                .filter(|d| is_version_dep(d) && skip_reason(&d.name).is_none())
                 .filter_map(|d| {
-                    for p in &locked {
-                        // The requested dependency may be present in the lock file with different versions,
-                        // but only one will be semver-compatible with the requested version.
-                        if d.name == p.name && d.req.matches(&p.version) {
-                            return Some((d.name, p.version.to_string()));
-                        }
-                    }
-                    None
+                    locked
+                        .iter()
+                        .find(|p| d.name == p.name && d.req.matches(&p.version))
+                        .map(|p| (d.name.clone(), p.version.to_string()))
                 })
-            {
-                manifest.upgrade(&Dependency::new(&name).set_version(&version), dry_run)?;
-            }
+                .collect::<Vec<_>>()
+        };
+
+        // Inherited dependencies share their requirement, so they are locked to a single version.
+        let inherited_versions = self
+            .0
+            .iter()
+            .flat_map(|(manifest, package)| {
+                let inherited = inherited_dependencies(manifest, package);
+                locked_versions(package)
+                    .into_iter()
+                    .filter(move |(name, _)| inherited.contains(name))
+            })
+            .collect::<HashMap<_, _>>();
+
+        if dry_run && !json {
+            dry_run_message()?;
         }
+
+        // Upgrade the manifests one at a time, as multiple manifests may
+        // request the same dependency at differing versions.
+        self.upgrade_each(json, |manifest, package, _inherited, is_root| {
+            let mut upgrades = Vec::new();
+            if let Some(package) = package {
+                for (name, version) in locked_versions(package) {
+                    upgrades.extend(manifest.upgrade(
+                        &Dependency::new(&name).set_version(&version),
+                        None,
+                        false,
+                        dry_run,
+                    )?);
+                }
+            }
+            if is_root {
+                for (name, version) in &inherited_versions {
+                    upgrades.extend(manifest.upgrade_workspace_dependency(
+                        &Dependency::new(name).set_version(version),
+                        None,
+                        false,
+                        dry_run,
+                    )?);
+                }
+            }
+            report_upgrades(manifest, &upgrades, json)
+        })?;
+
+        SkippedUpgrades(skipped).report(json);
         Ok(())
     }
 }
@@ -325,60 +675,124 @@ struct UpgradeMetadata {
     is_prerelease: bool,
 }
 
-/// The set of dependencies to be upgraded, alongside the registries returned from cargo metadata, and
-/// the desired versions, if specified by the user.
-struct DesiredUpgrades(HashMap<Dependency, UpgradeMetadata>);
+/// The set of dependencies to be upgraded, by name and current version requirement, alongside the
+/// registries returned from cargo metadata, and the desired versions, if specified by the user.
+/// Members of a workspace may require different versions of a crate, which are upgraded apart.
+struct DesiredUpgrades(HashMap<(Dependency, semver::VersionReq), UpgradeMetadata>);
 
 /// The complete specification of the upgrades that will be performed. Map of the dependency names
-/// to the new versions.
-struct ActualUpgrades(HashMap<Dependency, String>);
+/// and current requirements to the new versions, and whether the current requirements' operator
+/// and precision should be kept, which is not the case for versions given by the user.
+struct ActualUpgrades(HashMap<(Dependency, semver::VersionReq), (String, bool)>);
+
+/// The dependencies left alone because their latest release does not match their version
+/// requirement, with the requirement and the latest version.
+struct IncompatibleUpgrades(Vec<(String, String, String)>);
 
 impl DesiredUpgrades {
     /// Transform the dependencies into their upgraded forms. If a version is specified, all
-    /// dependencies will get that version.
-    fn get_upgraded(self, allow_prerelease: bool, manifest_path: &Path) -> Result<ActualUpgrades> {
-        self.0
-            .into_iter()
-            .map(
-                |(
-                    dep,
-                    UpgradeMetadata {
-                        registry,
-                        version,
-                        is_prerelease,
-                    },
-                )| {
-                    if let Some(v) = version {
-                        Ok((dep, v))
-                    } else {
-                        let registry_url = match registry {
-                            Some(x) => Some(Url::parse(&x).map_err(|_| {
-                                ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
-                            })?),
-                            None => None,
-                        };
-                        let allow_prerelease = allow_prerelease || is_prerelease;
-                        get_latest_dependency(
-                            &dep.name,
-                            allow_prerelease,
-                            manifest_path,
-                            &registry_url,
-                        )
-                        .map(|new_dep| {
-                            (
-                                dep,
-                                new_dep
-                                    .version()
-                                    .expect("Invalid dependency type")
-                                    .to_string(),
-                            )
-                        })
-                        .chain_err(|| "Failed to get new version")
-                    }
-                },
-            )
-            .collect::<Result<_>>()
-            .map(ActualUpgrades)
+    /// dependencies will get that version. Otherwise the latest version is looked up in the index
+    /// of the dependency's registry, taken from `indices` (`None` being the default registry).
+    ///
+    /// If `compatible` is set, dependencies are only upgraded to the latest version matching their
+    /// current requirement, and the ones with a newer, incompatible release are returned as well.
+    /// If `incompatible` is set, only the dependencies whose latest release doesn't match their
+    /// current requirement are upgraded.
+    fn get_upgraded(
+        self,
+        allow_prerelease: bool,
+        compatible: bool,
+        incompatible: bool,
+        indices: &HashMap<Option<String>, Box<dyn RegistryIndex>>,
+    ) -> Result<(ActualUpgrades, IncompatibleUpgrades)> {
+        let mut upgrades = HashMap::new();
+        let mut left_alone = Vec::new();
+        for (
+            (dep, req),
+            UpgradeMetadata {
+                registry,
+                version,
+                is_prerelease,
+            },
+        ) in self.0
+        {
+            if let Some(v) = version {
+                upgrades.insert((dep, req), (v, false));
+                continue;
+            }
+
+            let index = indices.get(&registry).ok_or_else(|| {
+                ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+            })?;
+            let allow_prerelease = allow_prerelease || is_prerelease;
+            let version = |new_dep: Dependency| {
+                new_dep
+                    .version()
+                    .expect("Invalid dependency type")
+                    .to_string()
+            };
+            let latest = get_latest_dependency(&dep.name, allow_prerelease, index.as_ref())
+                .map(version)
+                .chain_err(|| "Failed to get new version")?;
+            let is_compatible = semver::Version::parse(&latest)
+                .map(|latest| req.matches(&latest))
+                .unwrap_or(false);
+            if incompatible && is_compatible {
+                continue;
+            }
+            if !compatible || is_compatible {
+                upgrades.insert((dep, req), (latest, true));
+                continue;
+            }
+
+            left_alone.push((dep.name.clone(), req.to_string(), latest));
+            // The requirement may match no published version at all, leaving nothing to do.
+            if let Ok(new_dep) =
+                get_compatible_dependency(&dep.name, &req, allow_prerelease, index.as_ref())
+            {
+                upgrades.insert((dep, req), (version(new_dep), true));
+            }
+        }
+        left_alone.sort();
+        Ok((ActualUpgrades(upgrades), IncompatibleUpgrades(left_alone)))
+    }
+}
+
+/// The dependencies skipped before looking for upgrades, with their requirement and the reason.
+struct SkippedUpgrades(Vec<(String, String, String)>);
+
+impl SkippedUpgrades {
+    /// Print the skipped dependencies, if any, as JSON objects if `json` is set.
+    fn report(&self, json: bool) {
+        for (name, req, reason) in &self.0 {
+            if json {
+                let message = serde_json::json!({"crate": name, "from": req, "skipped": reason});
+                println!("{}", message);
+            } else {
+                println!("    {} ({}): {}, skipped", name, req, reason);
+            }
+        }
+    }
+}
+
+impl IncompatibleUpgrades {
+    /// Print the dependencies left alone, if any, as JSON objects if `json` is set.
+    fn report(&self, json: bool) {
+        if self.0.is_empty() {
+            return;
+        }
+        if !json {
+            println!("Incompatible releases available, not upgraded to:");
+        }
+        for (name, req, latest) in &self.0 {
+            if json {
+                let message =
+                    serde_json::json!({"crate": name, "from": req, "incompatible": latest});
+                println!("{}", message);
+            } else {
+                println!("    {} ({}): {}", name, req, latest);
+            }
+        }
     }
 }
 
@@ -391,14 +805,14 @@ fn process(args: Args) -> Result<()> {
         all,
         allow_prerelease,
         dry_run,
+        compatible,
+        incompatible,
+        pinned,
         to_lockfile,
+        ref exclude,
         ..
     } = args;
-
-    if !args.offline && !to_lockfile {
-        let url = registry_url(&find(&manifest_path)?, None)?;
-        update_registry_index(&url)?;
-    }
+    let json = args.message_format == "json";
 
     let manifests = if all {
         Manifests::get_all(&manifest_path)
@@ -406,30 +820,53 @@ fn process(args: Args) -> Result<()> {
         Manifests::get_local_one(&manifest_path)
     }?;
 
+    let mut config = CargoConfig::load(&find(&manifest_path)?, &args.config)?;
+    if args.offline {
+        config.set_offline();
+    }
+    if args.no_update {
+        config.set_no_update();
+    }
+
+    let ignored = manifests.get_ignored()?;
+
     if to_lockfile {
-        manifests.sync_to_lockfile(dry_run)
+        manifests.sync_to_lockfile(dry_run, config.offline(), json, exclude, &ignored)
     } else {
-        let existing_dependencies = manifests.get_dependencies(dependency)?;
-
-        // Update indices for any alternative registries, unless
-        // we're offline.
-        if !args.offline {
-            for registry_url in existing_dependencies
-                .0
-                .values()
-                .filter_map(|UpgradeMetadata { registry, .. }| registry.as_ref())
-                .collect::<HashSet<_>>()
-            {
-                update_registry_index(&Url::parse(registry_url).map_err(|_| {
-                    ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
-                })?)?;
-            }
+        let (existing_dependencies, skipped) =
+            manifests.get_dependencies(dependency, pinned, exclude, &ignored)?;
+
+        // Open the index of the default registry and of any alternative registries, updating
+        // them unless we're offline or they are fresh.
+        let mut indices = HashMap::new();
+        indices.insert(None, registry_index(&config.registry_url(None)?, &config)?);
+        for registry_url in existing_dependencies
+            .0
+            .values()
+            .filter_map(|UpgradeMetadata { registry, .. }| registry.as_ref())
+            .collect::<HashSet<_>>()
+        {
+            let url = Url::parse(registry_url).map_err(|_| {
+                ErrorKind::CargoEditLib(::cargo_edit::ErrorKind::InvalidCargoConfig)
+            })?;
+            let index = registry_index(&config.replaced_registry_url(&url)?, &config)?;
+            indices.insert(Some(registry_url.clone()), index);
+        }
+        for index in indices.values() {
+            index.update()?;
         }
 
-        let upgraded_dependencies =
-            existing_dependencies.get_upgraded(allow_prerelease, &find(&manifest_path)?)?;
+        let (upgraded_dependencies, left_alone) = existing_dependencies.get_upgraded(
+            allow_prerelease,
+            compatible,
+            incompatible,
+            &indices,
+        )?;
 
-        manifests.upgrade(&upgraded_dependencies, dry_run)
+        manifests.upgrade(&upgraded_dependencies, dry_run, json)?;
+        skipped.report(json);
+        left_alone.report(json);
+        Ok(())
     }
 }
 
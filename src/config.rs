@@ -0,0 +1,607 @@
+//! Loading of the cargo configuration
+//!
+//! Like cargo, the configuration is merged from several layers, from highest to lowest
+//! precedence: `--config` overrides, environment variables, `.cargo/config.toml` (or
+//! `.cargo/config`) files from the manifest's directory upwards, and the file in the cargo home
+//! directory. Registry tokens are read from `credentials.toml` in the cargo home directory too.
+//! ref: https://doc.rust-lang.org/cargo/reference/config.html
+
+use crate::errors::*;
+use crate::registry::{
+    local_source_url, CRATES_IO_INDEX, CRATES_IO_REGISTRY, CRATES_IO_SPARSE_INDEX,
+    DIRECTORY_PREFIX, LOCAL_REGISTRY_PREFIX,
+};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+/// How long a fetched git index is used before it is fetched again, by default
+const DEFAULT_INDEX_FRESHNESS: Duration = Duration::from_secs(60);
+
+/// A token authenticating with a registry, which is never printed
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Token(String);
+
+impl Token {
+    /// Get the token, to send it to the registry.
+    pub(crate) fn secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Token {
+    fn from(token: String) -> Self {
+        Token(token)
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Token(<redacted>)")
+    }
+}
+
+/// A `[registries.<name>]` table
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(crate) struct Registry {
+    pub(crate) index: Option<String>,
+    /// `git` or `sparse`, only meaningful for crates.io
+    pub(crate) protocol: Option<String>,
+    pub(crate) token: Option<Token>,
+}
+
+/// A `[source.<name>]` table
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(crate) struct Source {
+    #[serde(rename = "replace-with")]
+    pub(crate) replace_with: Option<String>,
+    pub(crate) registry: Option<String>,
+    #[serde(rename = "local-registry")]
+    pub(crate) local_registry: Option<PathBuf>,
+    pub(crate) directory: Option<PathBuf>,
+}
+
+/// The `[registry]` table
+#[derive(Debug, Default, Deserialize)]
+struct RegistryTable {
+    default: Option<String>,
+    /// The token of crates.io
+    token: Option<Token>,
+}
+
+/// The `[net]` table
+#[derive(Debug, Default, Deserialize)]
+struct NetTable {
+    #[serde(rename = "git-fetch-with-cli")]
+    git_fetch_with_cli: Option<bool>,
+    offline: Option<bool>,
+}
+
+/// A single layer of configuration, e.g. a config file
+#[derive(Debug, Default, Deserialize)]
+struct ConfigLayer {
+    #[serde(default)]
+    registries: HashMap<String, Registry>,
+    #[serde(default)]
+    source: HashMap<String, Source>,
+    #[serde(default)]
+    registry: RegistryTable,
+    #[serde(default)]
+    net: NetTable,
+}
+
+impl ConfigLayer {
+    /// Parse a layer, resolving relative paths against `base`.
+    fn parse(content: &str, base: &Path) -> Result<Self> {
+        let mut layer =
+            toml::from_str::<ConfigLayer>(content).map_err(|_| ErrorKind::InvalidCargoConfig)?;
+        for source in layer.source.values_mut() {
+            source.local_registry = source.local_registry.take().map(|path| base.join(path));
+            source.directory = source.directory.take().map(|path| base.join(path));
+        }
+        Ok(layer)
+    }
+}
+
+/// The cargo configuration applying to a manifest
+#[derive(Debug, Default)]
+pub struct CargoConfig {
+    /// Registries set with `CARGO_REGISTRIES_<NAME>_*` variables, by `<NAME>`
+    env_registries: HashMap<String, Registry>,
+    registries: HashMap<String, Registry>,
+    sources: HashMap<String, Source>,
+    default_registry: Option<String>,
+    crates_io_token: Option<Token>,
+    git_fetch_with_cli: Option<bool>,
+    offline: Option<bool>,
+    /// Set with `CARGO_EDIT_INDEX_FRESHNESS`, which is not part of cargo's configuration
+    index_freshness: Option<Duration>,
+    no_update: bool,
+}
+
+impl CargoConfig {
+    /// Load the configuration applying to the manifest at `manifest_path`.
+    ///
+    /// `overrides` are given like cargo's `--config` arguments: either `KEY=VALUE` pairs in TOML
+    /// syntax, e.g. `registries.my-registry.index="https://example.com/index"`, or paths to
+    /// additional config files.
+    pub fn load(manifest_path: &Path, overrides: &[String]) -> Result<Self> {
+        let mut config = CargoConfig::default();
+
+        let cwd = env::current_dir()?;
+        for value in overrides {
+            let layer = if value.contains('=') {
+                ConfigLayer::parse(value, &cwd)
+            } else {
+                let path = cwd.join(value);
+                let base = path.parent().unwrap_or(&cwd).to_path_buf();
+                fs::read_to_string(&path)
+                    .map_err(Error::from)
+                    .and_then(|content| ConfigLayer::parse(&content, &base))
+            }
+            .chain_err(|| format!("Invalid `--config` argument `{}`", value))?;
+            config.merge(layer);
+        }
+        config.merge_env(env::vars());
+        config.merge_file(&cargo_home()?, "credentials")?;
+
+        for work_dir in manifest_path
+            .parent()
+            .expect("there must be a parent directory")
+            .ancestors()
+        {
+            config.merge_file(&work_dir.join(".cargo"), "config")?;
+        }
+        config.merge_file(&cargo_home()?, "config")?;
+
+        Ok(config)
+    }
+
+    /// The registry to add dependencies from when none is given, set with `registry.default`
+    pub fn default_registry(&self) -> Option<&str> {
+        self.default_registry.as_deref()
+    }
+
+    /// Find the URL of a registry, or of crates.io if `registry` is `None`.
+    ///
+    /// Source replacement is followed to the end. If the registry is replaced with a
+    /// `local-registry` or `directory` source, the URL is that of the source's directory, prefixed
+    /// with `local-registry+` or `directory+` respectively.
+    pub fn registry_url(&self, registry: Option<&str>) -> Result<Url> {
+        // TODO support git sources: https://doc.rust-lang.org/cargo/reference/source-replacement.html?highlight=replace-with#source-replacement
+        let name = match registry {
+            Some(CRATES_IO_INDEX) | None => CRATES_IO_REGISTRY,
+            Some(r) => r,
+        };
+        let replace_with = self
+            .source(name)
+            .and_then(|source| source.replace_with.clone());
+        let mut source = if name == CRATES_IO_REGISTRY {
+            let protocol = self.registry(name).and_then(|r| r.protocol);
+            let index = match protocol.as_deref() {
+                Some("sparse") => CRATES_IO_SPARSE_INDEX,
+                _ => CRATES_IO_INDEX,
+            };
+            Source {
+                registry: Some(index.to_owned()),
+                replace_with,
+                ..Source::default()
+            }
+        } else if let Some(index) = self.registry(name).and_then(|r| r.index) {
+            Source {
+                registry: Some(index),
+                replace_with,
+                ..Source::default()
+            }
+        } else {
+            self.source(name)
+                .cloned()
+                .chain_err(|| ErrorKind::NoSuchRegistryFound(name.to_string()))?
+        };
+
+        // registry might be replaced with another source, which might be replaced in turn
+        let mut visited = HashSet::new();
+        while let Some(replace_with) = source.replace_with.take() {
+            if !visited.insert(replace_with.clone()) {
+                return Err(ErrorKind::InvalidCargoConfig.into());
+            }
+            source = self
+                .source(&replace_with)
+                .cloned()
+                .chain_err(|| ErrorKind::NoSuchSourceFound(replace_with.to_string()))?;
+        }
+
+        match source {
+            Source {
+                registry: Some(x), ..
+            } => Url::parse(&x).ok(),
+            Source {
+                local_registry: Some(path),
+                ..
+            } => local_source_url(LOCAL_REGISTRY_PREFIX, &path),
+            Source {
+                directory: Some(path),
+                ..
+            } => local_source_url(DIRECTORY_PREFIX, &path),
+            _ => None,
+        }
+        .chain_err(|| ErrorKind::InvalidCargoConfig)
+    }
+
+    /// Find the URL to look up the registry at `registry` in, e.g. a registry of a dependency
+    /// reported by `cargo metadata`, following the source replacement of the registry as
+    /// `registry_url` does. Registries that are not configured are looked up at their own URL.
+    pub fn replaced_registry_url(&self, registry: &Url) -> Result<Url> {
+        if [CRATES_IO_INDEX, CRATES_IO_SPARSE_INDEX].contains(&registry.as_str()) {
+            return self.registry_url(None);
+        }
+        let is_registry = |name: &&String| {
+            self.registry(name)
+                .and_then(|r| r.index)
+                .and_then(|index| Url::parse(&index).ok())
+                .as_ref()
+                == Some(registry)
+        };
+        match self.registries.keys().find(is_registry) {
+            Some(name) => self.registry_url(Some(name)),
+            None => Ok(registry.clone()),
+        }
+    }
+
+    /// Whether git indices are fetched with the `git` executable rather than natively, set with
+    /// `net.git-fetch-with-cli`
+    pub fn git_fetch_with_cli(&self) -> bool {
+        self.git_fetch_with_cli.unwrap_or(false)
+    }
+
+    /// Whether to work without accessing the network, set with `net.offline`
+    pub fn offline(&self) -> bool {
+        self.offline.unwrap_or(false)
+    }
+
+    /// Work without accessing the network, whatever the configuration says, as with `--offline`.
+    pub fn set_offline(&mut self) {
+        self.offline = Some(true);
+    }
+
+    /// How long a fetched git index is used before it is fetched again, set in seconds with the
+    /// `CARGO_EDIT_INDEX_FRESHNESS` environment variable. With `None`, an index is only fetched if
+    /// it is missing.
+    pub fn index_freshness(&self) -> Option<Duration> {
+        if self.no_update {
+            None
+        } else {
+            Some(self.index_freshness.unwrap_or(DEFAULT_INDEX_FRESHNESS))
+        }
+    }
+
+    /// Only fetch indices that are missing, as with `--no-update`.
+    pub fn set_no_update(&mut self) {
+        self.no_update = true;
+    }
+
+    /// Get the `[registries.<name>]` table of a registry.
+    pub(crate) fn registry(&self, name: &str) -> Option<Registry> {
+        let env = self.env_registries.get(&env_key(name));
+        let file = self.registries.get(name);
+        if env.is_none() && file.is_none() {
+            return None;
+        }
+        let (env, file) = (
+            env.cloned().unwrap_or_default(),
+            file.cloned().unwrap_or_default(),
+        );
+        // Registries in the files already hold the environment variables taking precedence.
+        Some(Registry {
+            index: file.index.or(env.index),
+            protocol: file.protocol.or(env.protocol),
+            token: file.token.or(env.token),
+        })
+    }
+
+    /// Get the token to authenticate with the registry at `registry`, if any.
+    ///
+    /// The token of crates.io is set with `registry.token`, the ones of other registries with
+    /// `registries.<name>.token`, where `registries.<name>.index` is `registry`.
+    pub(crate) fn registry_token(&self, registry: &Url) -> Option<Token> {
+        if [CRATES_IO_INDEX, CRATES_IO_SPARSE_INDEX].contains(&registry.as_str()) {
+            return self.crates_io_token.clone();
+        }
+        let is_registry = |r: &Registry| {
+            r.index
+                .as_ref()
+                .and_then(|index| Url::parse(index).ok())
+                .as_ref()
+                == Some(registry)
+        };
+        // Registries only set in the environment are known by their `<NAME>` there.
+        let env_only = self
+            .env_registries
+            .iter()
+            .filter(|(key, _)| !self.registries.keys().any(|name| env_key(name) == **key))
+            .map(|(_, registry)| registry.clone());
+        self.registries
+            .keys()
+            .filter_map(|name| self.registry(name))
+            .chain(env_only)
+            .find(is_registry)
+            .and_then(|registry| registry.token)
+    }
+
+    /// Get the `[source.<name>]` table of a source.
+    pub(crate) fn source(&self, name: &str) -> Option<&Source> {
+        self.sources.get(name)
+    }
+
+    /// Merge the file named `name` (e.g. `config`) in `dir`, if any, with relative paths resolved
+    /// against the parent of `dir`. If both exist, the file without the `.toml` extension is used,
+    /// as it is by cargo.
+    fn merge_file(&mut self, dir: &Path, name: &str) -> Result<()> {
+        let base = dir.parent().unwrap_or(dir);
+        for name in &[name.to_owned(), format!("{}.toml", name)] {
+            let path = dir.join(name);
+            if path.is_file() {
+                let layer = ConfigLayer::parse(&fs::read_to_string(&path)?, base)?;
+                self.merge(layer);
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge a layer of lower precedence than the ones merged so far.
+    fn merge(&mut self, layer: ConfigLayer) {
+        for (name, registry) in layer.registries {
+            // Environment variables take precedence over the layers merged after them.
+            let env = self
+                .env_registries
+                .get(&env_key(&name))
+                .cloned()
+                .unwrap_or_default();
+            let entry = self.registries.entry(name).or_default();
+            entry.index = entry.index.take().or(env.index).or(registry.index);
+            entry.protocol = entry.protocol.take().or(env.protocol).or(registry.protocol);
+            entry.token = entry.token.take().or(env.token).or(registry.token);
+        }
+        for (name, source) in layer.source {
+            self.sources.entry(name).or_insert(source);
+        }
+        if self.default_registry.is_none() {
+            self.default_registry = layer.registry.default;
+        }
+        if self.crates_io_token.is_none() {
+            self.crates_io_token = layer.registry.token;
+        }
+        if self.git_fetch_with_cli.is_none() {
+            self.git_fetch_with_cli = layer.net.git_fetch_with_cli;
+        }
+        if self.offline.is_none() {
+            self.offline = layer.net.offline;
+        }
+    }
+
+    /// Merge the `CARGO_REGISTRIES_<NAME>_INDEX`, `CARGO_REGISTRIES_<NAME>_PROTOCOL`,
+    /// `CARGO_REGISTRIES_<NAME>_TOKEN`, `CARGO_REGISTRY_*` and `CARGO_NET_*` environment
+    /// variables, as well as
+    /// `CARGO_EDIT_INDEX_FRESHNESS`. They are of lower precedence than the layers merged so far,
+    /// i.e. the `--config` overrides, and of higher precedence than the ones merged after them.
+    fn merge_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
+        for (key, value) in vars {
+            if key == "CARGO_REGISTRY_DEFAULT" {
+                self.default_registry.get_or_insert(value);
+            } else if key == "CARGO_REGISTRY_TOKEN" {
+                self.crates_io_token.get_or_insert(Token(value));
+            } else if key == "CARGO_NET_GIT_FETCH_WITH_CLI" {
+                self.git_fetch_with_cli.get_or_insert(value == "true");
+            } else if key == "CARGO_NET_OFFLINE" {
+                self.offline.get_or_insert(value == "true");
+            } else if key == "CARGO_EDIT_INDEX_FRESHNESS" {
+                self.index_freshness = value.parse().ok().map(Duration::from_secs);
+            } else if let Some(name) = key.strip_prefix("CARGO_REGISTRIES_") {
+                if let Some(name) = name.strip_suffix("_INDEX") {
+                    self.env_registries
+                        .entry(name.to_owned())
+                        .or_default()
+                        .index = Some(value);
+                } else if let Some(name) = name.strip_suffix("_PROTOCOL") {
+                    self.env_registries
+                        .entry(name.to_owned())
+                        .or_default()
+                        .protocol = Some(value);
+                } else if let Some(name) = name.strip_suffix("_TOKEN") {
+                    self.env_registries
+                        .entry(name.to_owned())
+                        .or_default()
+                        .token = Some(Token(value));
+                }
+            }
+        }
+    }
+}
+
+/// The name of a registry as written in environment variables, e.g. `MY_REGISTRY`
+fn env_key(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
+}
+
+/// Get the cargo home directory, from `CARGO_HOME` or in the home directory of the user.
+pub(crate) fn cargo_home() -> Result<PathBuf> {
+    let default_cargo_home = dirs::home_dir()
+        .map(|x| x.join(".cargo"))
+        .chain_err(|| ErrorKind::ReadHomeDirFailure)?;
+    let cargo_home = env::var("CARGO_HOME")
+        .map(PathBuf::from)
+        .unwrap_or(default_cargo_home);
+    Ok(cargo_home)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(content: &str) -> ConfigLayer {
+        ConfigLayer::parse(content, Path::new("/base")).unwrap()
+    }
+
+    #[test]
+    fn layers_of_higher_precedence_win() {
+        let mut config = CargoConfig::default();
+        config.merge(layer(
+            "registries.other.index = \"cli\"\nregistry.default = \"other\"",
+        ));
+        config.merge_env(vec![
+            (
+                "CARGO_REGISTRIES_MY_REGISTRY_INDEX".to_owned(),
+                "env".to_owned(),
+            ),
+            ("CARGO_REGISTRIES_OTHER_INDEX".to_owned(), "env".to_owned()),
+            (
+                "CARGO_REGISTRY_DEFAULT".to_owned(),
+                "my-registry".to_owned(),
+            ),
+        ]);
+        config.merge(layer(
+            "[registries.my-registry]\nindex = \"file\"\n\
+             [registries.other]\nindex = \"file\"\n\
+             [registries.crates-io]\nprotocol = \"sparse\"\n\
+             [registry]\ndefault = \"file\"\n",
+        ));
+
+        assert_eq!(config.default_registry(), Some("other"));
+        assert_eq!(
+            config.registry("my-registry").unwrap().index.as_deref(),
+            Some("env")
+        );
+        assert_eq!(
+            config.registry("other").unwrap().index.as_deref(),
+            Some("cli")
+        );
+        assert_eq!(
+            config.registry_url(None).unwrap().as_str(),
+            CRATES_IO_SPARSE_INDEX
+        );
+        assert!(config.registry("missing").is_none());
+    }
+
+    #[test]
+    fn git_fetch_with_cli() {
+        let mut config = CargoConfig::default();
+        assert!(!config.git_fetch_with_cli());
+        config.merge(layer("net.git-fetch-with-cli = true"));
+        assert!(config.git_fetch_with_cli());
+
+        let mut config = CargoConfig::default();
+        config.merge_env(vec![(
+            "CARGO_NET_GIT_FETCH_WITH_CLI".to_owned(),
+            "false".to_owned(),
+        )]);
+        config.merge(layer("net.git-fetch-with-cli = true"));
+        assert!(!config.git_fetch_with_cli());
+    }
+
+    #[test]
+    fn offline() {
+        let mut config = CargoConfig::default();
+        config.merge_env(vec![("CARGO_NET_OFFLINE".to_owned(), "true".to_owned())]);
+        config.merge(layer("net.offline = false"));
+        assert!(config.offline());
+
+        let mut config = CargoConfig::default();
+        config.merge(layer("net.offline = false"));
+        assert!(!config.offline());
+        config.set_offline();
+        assert!(config.offline());
+    }
+
+    #[test]
+    fn index_freshness() {
+        let mut config = CargoConfig::default();
+        assert_eq!(config.index_freshness(), Some(DEFAULT_INDEX_FRESHNESS));
+        config.merge_env(vec![(
+            "CARGO_EDIT_INDEX_FRESHNESS".to_owned(),
+            "0".to_owned(),
+        )]);
+        assert_eq!(config.index_freshness(), Some(Duration::from_secs(0)));
+        config.set_no_update();
+        assert_eq!(config.index_freshness(), None);
+    }
+
+    #[test]
+    fn registry_tokens() {
+        let mut config = CargoConfig::default();
+        config.merge_env(vec![
+            (
+                "CARGO_REGISTRIES_MY_REGISTRY_TOKEN".to_owned(),
+                "env".to_owned(),
+            ),
+            (
+                "CARGO_REGISTRIES_ENV_ONLY_INDEX".to_owned(),
+                "https://example.com/env-only".to_owned(),
+            ),
+            (
+                "CARGO_REGISTRIES_ENV_ONLY_TOKEN".to_owned(),
+                "env-only".to_owned(),
+            ),
+        ]);
+        config.merge(layer(
+            "[registries.my-registry]\nindex = \"https://example.com/index\"\n\
+             token = \"file\"\n\
+             [registry]\ntoken = \"crates-io\"\n",
+        ));
+
+        let token = |url: &str| {
+            config
+                .registry_token(&Url::parse(url).unwrap())
+                .map(|token| token.secret().to_owned())
+        };
+        assert_eq!(token("https://example.com/index").as_deref(), Some("env"));
+        assert_eq!(
+            token("https://example.com/env-only").as_deref(),
+            Some("env-only")
+        );
+        assert_eq!(token(CRATES_IO_SPARSE_INDEX).as_deref(), Some("crates-io"));
+        assert_eq!(token("https://example.com/other"), None);
+        assert!(!format!("{:?}", config).contains("crates-io\""));
+    }
+
+    #[test]
+    fn replaced_registry_urls() {
+        let mut config = CargoConfig::default();
+        config.merge(layer(
+            "[registries.my-registry]\nindex = \"https://example.com/index\"\n\
+             [source.my-registry]\nreplace-with = \"vendored\"\n\
+             [source.vendored]\nlocal-registry = \"vendor\"\n",
+        ));
+
+        let replaced = |url: &str| {
+            config
+                .replaced_registry_url(&Url::parse(url).unwrap())
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            replaced("https://example.com/index"),
+            "local-registry+file:///base/vendor/"
+        );
+        assert_eq!(replaced(CRATES_IO_INDEX), CRATES_IO_INDEX);
+        assert_eq!(
+            replaced("https://example.com/other"),
+            "https://example.com/other"
+        );
+    }
+
+    #[test]
+    fn relative_source_paths() {
+        let layer = layer("[source.vendored]\ndirectory = \"vendor\"\n");
+        assert_eq!(
+            layer.source["vendored"].directory.as_deref(),
+            Some(Path::new("/base/vendor"))
+        );
+    }
+}
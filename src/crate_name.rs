@@ -1,12 +1,16 @@
 //! Crate name parsing.
 use semver;
+use url::Url;
 
 use crate::errors::*;
 use crate::Dependency;
-use crate::{get_crate_name_from_github, get_crate_name_from_gitlab, get_crate_name_from_path};
+use crate::{get_crate_name_from_git, get_crate_name_from_path};
+
+/// The schemes of URLs taken for git repositories
+const GIT_SCHEMES: &[&str] = &["http", "https", "ssh", "git", "file"];
 
 /// A crate specifier. This can be a plain name (e.g. `docopt`), a name and a versionreq (e.g.
-/// `docopt@^0.8`), a URL, or a path.
+/// `docopt@^0.8`), the URL of a git repository, or a path.
 #[derive(Debug)]
 pub struct CrateName<'a>(&'a str);
 
@@ -23,20 +27,42 @@ impl<'a> CrateName<'a> {
 
     /// Does this specify a versionreq?
     pub fn has_version(&self) -> bool {
-        self.0.contains('@')
+        self.0.contains('@') && self.git_url().is_none()
     }
 
     /// Is this a URI?
     pub fn is_url_or_path(&self) -> bool {
-        self.is_github_url() || self.is_gitlab_url() || self.is_path()
-    }
-
-    fn is_github_url(&self) -> bool {
-        self.0.contains("https://github.com")
+        self.git_url().is_some() || self.is_path()
     }
 
-    fn is_gitlab_url(&self) -> bool {
-        self.0.contains("https://gitlab.com")
+    /// Get the URL of the git repository this specifies, if any.
+    ///
+    /// The scp-like syntax of git, e.g. `git@github.com:killercup/cargo-edit.git`, is turned into
+    /// an `ssh://` URL, which cargo understands as well.
+    fn git_url(&self) -> Option<String> {
+        if let Ok(url) = Url::parse(self.0) {
+            return if GIT_SCHEMES.contains(&url.scheme()) {
+                Some(self.0.to_owned())
+            } else {
+                None
+            };
+        }
+        let mut parts = self.0.splitn(2, ':');
+        let (user_host, path) = (parts.next()?, parts.next()?);
+        let path = path.trim_start_matches('/');
+        let mut user_and_host = user_host.splitn(2, '@');
+        let (user, host) = (user_and_host.next()?, user_and_host.next()?);
+        let is_name = |name: &str| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        };
+        if is_name(user) && is_name(host) && !path.is_empty() {
+            Some(format!("ssh://{}/{}", user_host, path))
+        } else {
+            None
+        }
     }
 
     fn is_path(&self) -> bool {
@@ -59,15 +85,16 @@ impl<'a> CrateName<'a> {
     }
 
     /// Will parse this crate name on the assumption that it is a URI.
-    pub fn parse_crate_name_from_uri(&self) -> Result<Dependency> {
-        if self.is_github_url() {
-            if let Ok(ref crate_name) = get_crate_name_from_github(self.0) {
-                return Ok(Dependency::new(crate_name).set_git(self.0));
-            }
-        } else if self.is_gitlab_url() {
-            if let Ok(ref crate_name) = get_crate_name_from_gitlab(self.0) {
-                return Ok(Dependency::new(crate_name).set_git(self.0));
+    ///
+    /// A git repository is fetched to read the crate name from its manifest, which fails when
+    /// `offline`.
+    pub fn parse_crate_name_from_uri(&self, offline: bool) -> Result<Dependency> {
+        if let Some(url) = self.git_url() {
+            if offline {
+                return Err(ErrorKind::Offline(url).into());
             }
+            let crate_name = get_crate_name_from_git(&url)?;
+            return Ok(Dependency::new(&crate_name).set_git(&url));
         } else if self.is_path() {
             if let Ok(ref crate_name) = get_crate_name_from_path(self.0) {
                 return Ok(Dependency::new(crate_name).set_path(self.0));
@@ -77,3 +104,30 @@ impl<'a> CrateName<'a> {
         bail!("Unable to obtain crate informations from `{}`.\n", self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn git_urls() {
+        let git_url = |name| CrateName::new(name).git_url();
+        for url in &[
+            "https://github.com/killercup/cargo-edit",
+            "https://gitea.example.com/org/repo.git",
+            "ssh://git@bitbucket.org/org/repo.git",
+            "file:///srv/git/repo",
+        ] {
+            assert_eq!(git_url(url).as_deref(), Some(*url));
+        }
+        assert_eq!(
+            git_url("git@github.com:killercup/cargo-edit.git").as_deref(),
+            Some("ssh://git@github.com/killercup/cargo-edit.git")
+        );
+        assert!(!CrateName::new("git@github.com:killercup/cargo-edit.git").has_version());
+
+        for name in &["docopt", "docopt@0.8", "../cargo-edit", "C:\\cargo-edit"] {
+            assert_eq!(git_url(name), None);
+        }
+    }
+}
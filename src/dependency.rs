@@ -1,5 +1,8 @@
 use toml_edit;
 
+use crate::errors::*;
+use crate::registry::GitReference;
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 enum DependencySource {
     Version {
@@ -7,9 +10,31 @@ enum DependencySource {
         path: Option<String>,
         registry: Option<String>,
     },
-    Git(String),
+    Git {
+        repo: String,
+        reference: Option<GitReference>,
+        version: Option<String>,
+    },
+    Workspace,
 }
 
+/// The keys of a dependency entry that `Dependency` models
+const KNOWN_KEYS: &[&str] = &[
+    "version",
+    "path",
+    "registry",
+    "git",
+    "branch",
+    "tag",
+    "rev",
+    "workspace",
+    "optional",
+    "default-features",
+    "default_features",
+    "features",
+    "package",
+];
+
 /// A dependency handled by Cargo
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub struct Dependency {
@@ -18,9 +43,14 @@ pub struct Dependency {
     optional: bool,
     default_features: bool,
     source: DependencySource,
+    /// Features to enable on the dependency, if any.
+    features: Option<Vec<String>>,
     /// If the dependency is renamed, this is the new name for the dependency
     /// as a string.  None if it is not renamed.
     rename: Option<String>,
+    /// Keys of the entry that are not modelled above (e.g. `public`), with their values in TOML
+    /// syntax, so that they are written back as they were read.
+    extra: Vec<(String, String)>,
 }
 
 impl Default for Dependency {
@@ -35,6 +65,8 @@ impl Default for Dependency {
                 path: None,
                 registry: None,
             },
+            features: None,
+            extra: Vec::new(),
         }
     }
 }
@@ -68,7 +100,32 @@ impl Dependency {
 
     /// Set dependency to a given repository
     pub fn set_git(mut self, repo: &str) -> Dependency {
-        self.source = DependencySource::Git(repo.into());
+        self.source = DependencySource::Git {
+            repo: repo.into(),
+            reference: None,
+            version: None,
+        };
+        self
+    }
+
+    /// Set the branch, tag or revision of a git dependency
+    ///
+    /// This has no effect unless the dependency was set to a git repository with `set_git`.
+    pub fn set_git_reference(mut self, git_reference: GitReference) -> Dependency {
+        if let DependencySource::Git {
+            ref mut reference, ..
+        } = self.source
+        {
+            *reference = Some(git_reference);
+        }
+        self
+    }
+
+    /// Inherit the dependency from the `[workspace.dependencies]` table of the workspace root
+    ///
+    /// This replaces any version, path, git repository or registry of the dependency.
+    pub fn set_inherited(mut self) -> Dependency {
+        self.source = DependencySource::Workspace;
         self
     }
 
@@ -98,6 +155,12 @@ impl Dependency {
         self
     }
 
+    /// Set the list of features to enable on the dependency
+    pub fn set_features(mut self, features: Option<Vec<String>>) -> Dependency {
+        self.features = features;
+        self
+    }
+
     /// Set the alias for the dependency
     pub fn set_rename(mut self, rename: &str) -> Dependency {
         self.rename = Some(rename.into());
@@ -127,17 +190,80 @@ impl Dependency {
 
     /// Get version of dependency
     pub fn version(&self) -> Option<&str> {
-        if let DependencySource::Version {
-            version: Some(ref version),
-            ..
-        } = self.source
-        {
-            Some(version)
-        } else {
-            None
+        match self.source {
+            DependencySource::Version {
+                version: Some(ref version),
+                ..
+            }
+            | DependencySource::Git {
+                version: Some(ref version),
+                ..
+            } => Some(version),
+            _ => None,
+        }
+    }
+
+    /// Get the path of the dependency (if any)
+    pub fn path(&self) -> Option<&str> {
+        match self.source {
+            DependencySource::Version {
+                path: Some(ref path),
+                ..
+            } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Get the registry of the dependency (if any)
+    pub fn registry(&self) -> Option<&str> {
+        match self.source {
+            DependencySource::Version {
+                registry: Some(ref registry),
+                ..
+            } => Some(registry),
+            _ => None,
         }
     }
 
+    /// Get the git repository of the dependency (if any)
+    pub fn git(&self) -> Option<&str> {
+        match self.source {
+            DependencySource::Git { ref repo, .. } => Some(repo),
+            _ => None,
+        }
+    }
+
+    /// Get the branch, tag or revision of a git dependency (if any)
+    pub fn git_reference(&self) -> Option<&GitReference> {
+        match self.source {
+            DependencySource::Git {
+                reference: Some(ref reference),
+                ..
+            } => Some(reference),
+            _ => None,
+        }
+    }
+
+    /// Get whether the dependency is inherited from the workspace
+    pub fn inherited(&self) -> bool {
+        self.source == DependencySource::Workspace
+    }
+
+    /// Get whether the dependency is optional
+    pub fn optional(&self) -> bool {
+        self.optional
+    }
+
+    /// Get the value of default-features for the dependency
+    pub fn default_features(&self) -> bool {
+        self.default_features
+    }
+
+    /// Get the features enabled on the dependency (if any)
+    pub fn features(&self) -> Option<&[String]> {
+        self.features.as_deref()
+    }
+
     /// Get the alias for the dependency (if any)
     pub fn rename(&self) -> Option<&str> {
         match &self.rename {
@@ -149,14 +275,15 @@ impl Dependency {
     /// Convert dependency to TOML
     ///
     /// Returns a tuple with the dependency's name and either the version as a `String`
-    /// or the path/git repository as an `InlineTable`.
-    /// (If the dependency is set as `optional` or `default-features` is set to `false`,
-    /// an `InlineTable` is returned in any case.)
+    /// or the path/git repository/`workspace = true` as an `InlineTable`.
+    /// (If the dependency is set as `optional`, `default-features` is set to `false` or
+    /// `features` are given, an `InlineTable` is returned in any case.)
     pub fn to_toml(&self) -> (String, toml_edit::Item) {
         let data: toml_edit::Item = match (
             self.optional,
             self.default_features,
             self.source.clone(),
+            self.features.as_ref(),
             self.rename.as_ref(),
         ) {
             // Extra short when version flag only
@@ -169,9 +296,10 @@ impl Dependency {
                     registry: None,
                 },
                 None,
-            ) => toml_edit::value(v),
+                None,
+            ) if self.extra.is_empty() => toml_edit::value(v),
             // Other cases are represented as an inline table
-            (optional, default_features, source, rename) => {
+            (optional, default_features, source, features, rename) => {
                 let mut data = toml_edit::InlineTable::default();
 
                 match source {
@@ -190,19 +318,54 @@ impl Dependency {
                             data.get_or_insert("registry", r);
                         }
                     }
-                    DependencySource::Git(v) => {
-                        data.get_or_insert("git", v);
+                    DependencySource::Git {
+                        repo,
+                        reference,
+                        version,
+                    } => {
+                        if let Some(v) = version {
+                            data.get_or_insert("version", v);
+                        }
+                        data.get_or_insert("git", repo);
+                        match reference {
+                            Some(GitReference::Branch(b)) => {
+                                data.get_or_insert("branch", b);
+                            }
+                            Some(GitReference::Tag(t)) => {
+                                data.get_or_insert("tag", t);
+                            }
+                            Some(GitReference::Rev(r)) => {
+                                data.get_or_insert("rev", r);
+                            }
+                            None => {}
+                        }
+                    }
+                    DependencySource::Workspace => {
+                        data.get_or_insert("workspace", true);
                     }
                 }
+                let inherited = self.inherited();
                 if self.optional {
                     data.get_or_insert("optional", optional);
                 }
-                if !self.default_features {
+                // `default-features` and `package` of inherited dependencies can only be set in
+                // the workspace root
+                if !self.default_features && !inherited {
                     data.get_or_insert("default-features", default_features);
                 }
-                if rename.is_some() {
+                if let Some(features) = features {
+                    let features: toml_edit::Value = features.iter().map(String::as_str).collect();
+                    data.get_or_insert("features", features);
+                }
+                if rename.is_some() && !inherited {
                     data.get_or_insert("package", self.name.clone());
                 }
+                for (key, value) in &self.extra {
+                    let value = value
+                        .parse::<toml_edit::Value>()
+                        .expect("extra keys are read from valid TOML");
+                    data.get_or_insert(key, value);
+                }
 
                 data.fmt();
                 toml_edit::value(toml_edit::Value::InlineTable(data))
@@ -211,11 +374,154 @@ impl Dependency {
 
         (self.name_in_manifest().to_string(), data)
     }
+
+    /// Read a dependency from a TOML entry
+    ///
+    /// `key` is the name the dependency is known by in the manifest, and `item` is its value:
+    /// either a version string, an inline table or a full table. This is the inverse of
+    /// `Dependency::to_toml`. Keys that `Dependency` does not model are kept and written back by
+    /// `to_toml`, unless they hold a table that cannot be written inline, which is an error.
+    pub fn from_toml(key: &str, item: &toml_edit::Item) -> Result<Dependency> {
+        let invalid = |reason: &str| -> Error {
+            ErrorKind::InvalidDependency(key.into(), reason.into()).into()
+        };
+
+        if let Some(version) = item.as_str() {
+            return Ok(Dependency {
+                source: DependencySource::Version {
+                    version: Some(version.into()),
+                    path: None,
+                    registry: None,
+                },
+                ..Dependency::new(key)
+            });
+        }
+
+        let table = item
+            .as_table_like()
+            .ok_or_else(|| invalid("expected a version string or a table"))?;
+        let get_str = |field: &str| -> Result<Option<String>> {
+            match table.get(field) {
+                None => Ok(None),
+                Some(value) if value.is_none() => Ok(None),
+                Some(value) => value
+                    .as_str()
+                    .map(|s| Some(s.to_owned()))
+                    .ok_or_else(|| invalid(&format!("`{}` must be a string", field))),
+            }
+        };
+        let get_bool = |field: &str| -> Result<Option<bool>> {
+            match table.get(field) {
+                None => Ok(None),
+                Some(value) if value.is_none() => Ok(None),
+                Some(value) => value
+                    .as_bool()
+                    .map(Some)
+                    .ok_or_else(|| invalid(&format!("`{}` must be a boolean", field))),
+            }
+        };
+
+        let version = get_str("version")?;
+        let path = get_str("path")?;
+        let registry = get_str("registry")?;
+        let git = get_str("git")?;
+        // Git references only apply to git dependencies, and are kept as they are otherwise.
+        let is_git = git.is_some();
+        let source = if let Some(workspace) = get_bool("workspace")? {
+            if !workspace {
+                return Err(invalid("`workspace` can only be `true`"));
+            }
+            if version.is_some() || path.is_some() || registry.is_some() || git.is_some() {
+                return Err(invalid(
+                    "`workspace` cannot be combined with `version`, `path`, `registry` or `git`",
+                ));
+            }
+            DependencySource::Workspace
+        } else if let Some(repo) = git {
+            if path.is_some() || registry.is_some() {
+                return Err(invalid(
+                    "`git` cannot be combined with `path` or `registry`",
+                ));
+            }
+            let references = [
+                get_str("branch")?.map(GitReference::Branch),
+                get_str("tag")?.map(GitReference::Tag),
+                get_str("rev")?.map(GitReference::Rev),
+            ];
+            let mut references = references.iter().flatten();
+            let reference = references.next().cloned();
+            if references.next().is_some() {
+                return Err(invalid(
+                    "only one of `branch`, `tag` or `rev` may be specified",
+                ));
+            }
+            DependencySource::Git {
+                repo,
+                reference,
+                version,
+            }
+        } else {
+            DependencySource::Version {
+                version,
+                path,
+                registry,
+            }
+        };
+
+        let features = match table.get("features") {
+            None => None,
+            Some(value) if value.is_none() => None,
+            Some(value) => Some(
+                value
+                    .as_array()
+                    .and_then(|features| {
+                        features
+                            .iter()
+                            .map(|f| f.as_str().map(String::from))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(|| invalid("`features` must be an array of strings"))?,
+            ),
+        };
+        let default_features = match get_bool("default-features")? {
+            Some(default_features) => Some(default_features),
+            None => get_bool("default_features")?,
+        };
+
+        let (name, rename) = match get_str("package")? {
+            Some(package) => (package, Some(key.to_owned())),
+            None => (key.to_owned(), None),
+        };
+
+        let mut extra = Vec::new();
+        for (field, value) in table.iter() {
+            let is_reference = ["branch", "tag", "rev"].contains(&field);
+            if value.is_none() || (KNOWN_KEYS.contains(&field) && (is_git || !is_reference)) {
+                continue;
+            }
+            let value = value
+                .as_value()
+                .ok_or_else(|| invalid(&format!("`{}` must be a value", field)))?;
+            let value = toml_edit::decorated(value.clone(), "", "");
+            extra.push((field.to_owned(), value.to_string()));
+        }
+
+        Ok(Dependency {
+            name,
+            optional: get_bool("optional")?.unwrap_or(false),
+            default_features: default_features.unwrap_or(true),
+            source,
+            features,
+            rename,
+            extra,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::dependency::Dependency;
+    use crate::registry::GitReference;
 
     #[test]
     fn to_toml_simple_dep() {
@@ -254,6 +560,25 @@ mod tests {
         assert_eq!(dep.get("default-features").unwrap().as_bool(), Some(false));
     }
 
+    #[test]
+    fn to_toml_dep_with_features() {
+        let toml = Dependency::new("dep")
+            .set_version("1.0")
+            .set_features(Some(vec!["rt".to_owned(), "macros".to_owned()]))
+            .to_toml();
+
+        assert_eq!(toml.0, "dep".to_owned());
+        assert!(toml.1.is_inline_table());
+
+        let dep = toml.1.as_inline_table().unwrap();
+        assert_eq!(dep.get("version").unwrap().as_str(), Some("1.0"));
+        let features = dep.get("features").unwrap().as_array().unwrap();
+        assert_eq!(
+            features.iter().map(|f| f.as_str()).collect::<Vec<_>>(),
+            vec![Some("rt"), Some("macros")]
+        );
+    }
+
     #[test]
     fn to_toml_dep_with_path_source() {
         let toml = Dependency::new("dep").set_path("~/foo/bar").to_toml();
@@ -281,6 +606,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn to_toml_dep_with_git_tag() {
+        let toml = Dependency::new("dep")
+            .set_git("https://foor/bar.git")
+            .set_git_reference(GitReference::Tag("v1.2".to_owned()))
+            .to_toml();
+
+        assert_eq!(toml.0, "dep".to_owned());
+        assert!(toml.1.is_inline_table());
+
+        let dep = toml.1.as_inline_table().unwrap();
+        assert_eq!(
+            dep.get("git").unwrap().as_str(),
+            Some("https://foor/bar.git")
+        );
+        assert_eq!(dep.get("tag").unwrap().as_str(), Some("v1.2"));
+        assert!(dep.get("branch").is_none());
+        assert!(dep.get("rev").is_none());
+    }
+
+    #[test]
+    fn to_toml_inherited_dep() {
+        let toml = Dependency::new("dep")
+            .set_version("1.0")
+            .set_inherited()
+            .set_default_features(false)
+            .set_features(Some(vec!["rt".to_owned()]))
+            .to_toml();
+
+        assert_eq!(toml.0, "dep".to_owned());
+        assert!(toml.1.is_inline_table());
+
+        let dep = toml.1.as_inline_table().unwrap();
+        assert_eq!(dep.get("workspace").unwrap().as_bool(), Some(true));
+        assert!(dep.get("version").is_none());
+        assert!(dep.get("default-features").is_none());
+        assert_eq!(dep.get("features").unwrap().as_array().unwrap().len(), 1);
+    }
+
     #[test]
     fn to_toml_renamed_dep() {
         let toml = Dependency::new("dep").set_rename("d").to_toml();
@@ -303,6 +667,161 @@ mod tests {
         assert_eq!(dep.get("registry").unwrap().as_str(), Some("alternative"));
     }
 
+    #[test]
+    fn from_toml_version_string() {
+        let dep = Dependency::from_toml("dep", &toml_edit::value("1.0")).unwrap();
+
+        assert_eq!(dep, Dependency::new("dep").set_version("1.0"));
+    }
+
+    #[test]
+    fn from_toml_inline_table() {
+        let manifest: toml_edit::Document = r#"
+[dependencies]
+d = { version = "1.0", package = "dep", optional = true, default-features = false, features = ["a", "b"] }
+"#
+        .parse()
+        .unwrap();
+        let dep = Dependency::from_toml("d", &manifest["dependencies"]["d"]).unwrap();
+
+        assert_eq!(dep.name, "dep");
+        assert_eq!(dep.rename(), Some("d"));
+        assert_eq!(dep.version(), Some("1.0"));
+        assert!(dep.optional());
+        assert!(!dep.default_features());
+        assert_eq!(dep.features(), Some(&["a".to_owned(), "b".to_owned()][..]));
+    }
+
+    #[test]
+    fn from_toml_full_table() {
+        let manifest: toml_edit::Document = r#"
+[dependencies.dep]
+version = "0.3"
+git = "https://foor/bar.git"
+rev = "0123abc"
+"#
+        .parse()
+        .unwrap();
+        let dep = Dependency::from_toml("dep", &manifest["dependencies"]["dep"]).unwrap();
+
+        assert_eq!(dep.version(), Some("0.3"));
+        assert_eq!(dep.git(), Some("https://foor/bar.git"));
+        assert_eq!(
+            dep.git_reference(),
+            Some(&GitReference::Rev("0123abc".to_owned()))
+        );
+    }
+
+    #[test]
+    fn from_toml_invalid_entries() {
+        let manifest: toml_edit::Document = r#"
+[dependencies]
+a = 1
+b = { version = 1 }
+c = { git = "https://foor/bar.git", path = "../c" }
+d = { git = "https://foor/bar.git", branch = "dev", tag = "v1" }
+e = { features = "a" }
+f = { workspace = false }
+g = { workspace = true, version = "1.0" }
+"#
+        .parse()
+        .unwrap();
+
+        for key in &["a", "b", "c", "d", "e", "f", "g"] {
+            assert!(Dependency::from_toml(key, &manifest["dependencies"][key]).is_err());
+        }
+    }
+
+    #[test]
+    fn from_toml_round_trip() {
+        let deps = vec![
+            Dependency::new("dep").set_version("1.0"),
+            Dependency::new("dep")
+                .set_path("../dep")
+                .set_optional(true)
+                .set_features(Some(vec!["a".to_owned()])),
+            Dependency::new("dep")
+                .set_version("0.2")
+                .set_registry("alternative")
+                .set_default_features(false)
+                .set_rename("d"),
+            Dependency::new("dep")
+                .set_git("https://foor/bar.git")
+                .set_git_reference(GitReference::Branch("dev".to_owned())),
+            Dependency::new("dep")
+                .set_inherited()
+                .set_optional(true)
+                .set_features(Some(vec!["a".to_owned()])),
+        ];
+
+        for dep in deps {
+            let (key, item) = dep.to_toml();
+            assert_eq!(Dependency::from_toml(&key, &item).unwrap(), dep);
+        }
+    }
+
+    #[test]
+    fn from_toml_keeps_unknown_keys() {
+        let manifest: toml_edit::Document = r#"
+[dependencies]
+a = { version = "1.0", public = true, artifact = { kind = "bin" } }
+
+[dependencies.b]
+version = "0.2"
+lib = false
+
+[dependencies.c.nested]
+key = "value"
+"#
+        .parse()
+        .unwrap();
+
+        let dep = Dependency::from_toml("a", &manifest["dependencies"]["a"]).unwrap();
+        let (key, item) = dep.to_toml();
+        assert_eq!(
+            item.as_inline_table().unwrap().to_string().trim(),
+            r#"{ version = "1.0", public = true, artifact = { kind = "bin" } }"#
+        );
+        assert_eq!(Dependency::from_toml(&key, &item).unwrap(), dep);
+
+        let dep = Dependency::from_toml("b", &manifest["dependencies"]["b"]).unwrap();
+        assert_eq!(
+            dep.to_toml()
+                .1
+                .as_inline_table()
+                .unwrap()
+                .to_string()
+                .trim(),
+            r#"{ version = "0.2", lib = false }"#
+        );
+
+        assert!(Dependency::from_toml("c", &manifest["dependencies"]["c"]).is_err());
+    }
+
+    #[test]
+    fn from_toml_keeps_git_references_of_other_sources() {
+        let manifest: toml_edit::Document = r#"
+[dependencies]
+a = { path = "../a", branch = "dev" }
+b = { version = "1.0", rev = "0123abc" }
+"#
+        .parse()
+        .unwrap();
+
+        for (key, expected) in &[
+            ("a", r#"{ path = "../a", branch = "dev" }"#),
+            ("b", r#"{ version = "1.0", rev = "0123abc" }"#),
+        ] {
+            let dep = Dependency::from_toml(key, &manifest["dependencies"][key]).unwrap();
+            let (key, item) = dep.to_toml();
+            assert_eq!(
+                item.as_inline_table().unwrap().to_string().trim(),
+                *expected
+            );
+            assert_eq!(Dependency::from_toml(&key, &item).unwrap(), dep);
+        }
+    }
+
     #[test]
     fn to_toml_complex_dep() {
         let toml = Dependency::new("dep")
@@ -46,6 +46,11 @@ error_chain! {
             description("Found virtual manifest, but this command requires running against an \
                          actual package in this workspace.")
         }
+        /// No workspace root could be found for a package
+        MissingWorkspaceRoot(manifest: String) {
+            description("Unable to find the workspace root")
+            display("Could not find the workspace root of `{}`.", manifest)
+        }
         /// The TOML table could not be found.
         NonExistentTable(table: String) {
             description("non existent table")
@@ -56,6 +61,48 @@ error_chain! {
             description("non existent dependency")
             display("The dependency `{}` could not be found in `{}`.", name, table)
         }
+        /// The dependency entry could not be understood.
+        InvalidDependency(name: String, reason: String) {
+            description("invalid dependency")
+            display("The dependency `{}` is invalid: {}.", name, reason)
+        }
+        /// The dependency could not be found in any table of the manifest.
+        NonExistentDependencyInManifest(name: String) {
+            description("non existent dependency")
+            display("The dependency `{}` could not be found in any dependency table.", name)
+        }
+        /// A git registry index could not be fetched
+        IndexFetchFailed(url: String) {
+            description("Failed to fetch the registry index")
+            display("Failed to fetch the registry index `{}`.", url)
+        }
+        /// The network was about to be accessed in offline mode
+        Offline(url: String) {
+            description("Cannot access the network in offline mode")
+            display("Cannot access `{}` in offline mode.", url)
+        }
+        /// A registry index is needed in offline mode, but has never been downloaded
+        IndexNotDownloaded(url: String) {
+            description("The registry index has not been downloaded")
+            display(
+                "The registry index `{}` has not been downloaded, so it cannot be used offline.",
+                url
+            )
+        }
+        /// A registry index refused access, for lack of a valid token
+        IndexUnauthorized(url: String) {
+            description("The registry index refused access")
+            display(
+                "The registry index `{}` refused access. Check its token, set in \
+                 `credentials.toml` or with `CARGO_REGISTRIES_<NAME>_TOKEN`.",
+                url
+            )
+        }
+        /// A request to a sparse registry index failed
+        IndexRequestFailed(url: String) {
+            description("Failed to fetch from the registry index")
+            display("Failed to fetch `{}` from the registry index.", url)
+        }
         /// Config of cargo is invalid
         InvalidCargoConfig {
             description("Invalid cargo config")
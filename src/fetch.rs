@@ -1,68 +1,81 @@
+use crate::config::cargo_home;
 use crate::errors::*;
-use crate::registry::{registry_path, registry_path_from_url};
+use crate::index::{CrateVersion, RegistryIndex};
+use crate::registry::registry_path_from_url;
+use crate::sparse::is_sparse;
 use crate::{Dependency, Manifest};
 use env_proxy;
+use fs2::FileExt;
 use regex::Regex;
 use reqwest;
-use semver;
-use std::env;
+use std::fs;
 use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::time::Duration;
+use tempdir::TempDir;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 use url::Url;
 
-#[derive(Deserialize)]
-struct CrateVersion {
-    name: String,
-    #[serde(rename = "vers")]
-    version: semver::Version,
-    yanked: bool,
-}
-
 /// Query latest version from a registry index
 ///
-/// The registry argument must be specified for crates
-/// from alternative registries.
+/// The index of the registry the crate is published in has to be given, see `registry_index`.
+///
+/// The latest version will be returned as a `Dependency`. If the index is used offline, the latest
+/// of the versions already downloaded is preferred, like cargo does.
 ///
-/// The latest version will be returned as a `Dependency`. This will fail, when
+/// This will fail, when
 ///
-/// - there is no Internet connection and offline is false.
+/// - the index cannot be read, e.g. there is no Internet connection for a sparse index.
 /// - summaries in registry index with an incorrect format.
 /// - a crate with the given name does not exist on the registry.
 pub fn get_latest_dependency(
     crate_name: &str,
     flag_allow_prerelease: bool,
-    manifest_path: &Path,
-    registry: &Option<Url>,
+    index: &dyn RegistryIndex,
 ) -> Result<Dependency> {
-    if env::var("CARGO_IS_TEST").is_ok() {
-        // We are in a simulated reality. Nothing is real here.
-        // FIXME: Use actual test handling code.
-        let new_version = if flag_allow_prerelease {
-            format!("{}--PRERELEASE_VERSION_TEST", crate_name)
-        } else {
-            format!("{}--CURRENT_VERSION_TEST", crate_name)
-        };
+    get_latest_matching_dependency(crate_name, flag_allow_prerelease, index, |_| true)
+}
 
-        return Ok(Dependency::new(crate_name).set_version(&new_version));
-    }
+/// Query the latest version matching a version requirement from a registry index
+///
+/// Like `get_latest_dependency`, but only versions matched by `version_req` are considered, e.g.
+/// `1.9.3` for `1.2`. This fails if there are none.
+pub fn get_compatible_dependency(
+    crate_name: &str,
+    version_req: &semver::VersionReq,
+    flag_allow_prerelease: bool,
+    index: &dyn RegistryIndex,
+) -> Result<Dependency> {
+    get_latest_matching_dependency(crate_name, flag_allow_prerelease, index, |v| {
+        version_req.matches(&v.version)
+    })
+}
 
+fn get_latest_matching_dependency(
+    crate_name: &str,
+    flag_allow_prerelease: bool,
+    index: &dyn RegistryIndex,
+    matches: impl Fn(&CrateVersion) -> bool,
+) -> Result<Dependency> {
     if crate_name.is_empty() {
         return Err(ErrorKind::EmptyCrateName.into());
     }
 
-    let registry_path = match registry {
-        Some(url) => registry_path_from_url(url)?,
-        None => registry_path(manifest_path, None)?,
-    };
-
-    let crate_versions = fuzzy_query_registry_index(crate_name, &registry_path)?;
+    let crate_versions = fuzzy_query_registry_index(crate_name, index)?
+        .into_iter()
+        .filter(|v| matches(v))
+        .collect::<Vec<_>>();
 
-    let dep = read_latest_version(&crate_versions, flag_allow_prerelease)?;
+    let downloaded = crate_versions
+        .iter()
+        .filter(|&v| index.is_offline() && index.is_downloaded(v))
+        .cloned()
+        .collect::<Vec<_>>();
+    let dep = read_latest_version(&downloaded, flag_allow_prerelease)
+        .or_else(|_| read_latest_version(&crate_versions, flag_allow_prerelease))?;
 
     if dep.name != crate_name {
-        println!("WARN: Added `{}` instead of `{}`", dep.name, crate_name);
+        eprintln!("WARN: Added `{}` instead of `{}`", dep.name, crate_name);
     }
 
     Ok(dep)
@@ -90,46 +103,173 @@ fn read_latest_version(
     Ok(Dependency::new(name).set_version(&version))
 }
 
+/// The reference the fetched `HEAD` of a registry index is stored under
+pub(crate) const INDEX_HEAD: &str = "refs/remotes/origin/HEAD";
+/// The reference older versions stored the `master` branch of a registry index under
+pub(crate) const LEGACY_INDEX_HEAD: &str = "refs/remotes/origin/master";
+
 /// update registry index for given project
-pub fn update_registry_index(registry: &Url) -> Result<()> {
+///
+/// A missing index is cloned first. Sparse registries are queried on demand, so there is nothing
+/// to update for them.
+///
+/// The index is fetched with `git2`, or with the `git` executable if `git_fetch_with_cli` is set
+/// (see `net.git-fetch-with-cli` in the cargo config). The package cache of cargo is locked
+/// meanwhile, see `lock_package_cache`.
+pub fn update_registry_index(registry: &Url, git_fetch_with_cli: bool) -> Result<()> {
+    let _lock = lock_package_cache()?;
+    fetch_registry_index(registry, git_fetch_with_cli, None)
+}
+
+/// Like `update_registry_index`, for a caller holding the lock of the package cache already.
+///
+/// A `token` is offered as password when the registry asks for credentials, unless the index is
+/// fetched with the `git` executable, which uses the credential helpers of git only.
+pub(crate) fn fetch_registry_index(
+    registry: &Url,
+    git_fetch_with_cli: bool,
+    token: Option<&str>,
+) -> Result<()> {
+    if is_sparse(registry) {
+        return Ok(());
+    }
     let registry_path = registry_path_from_url(registry)?;
 
-    let colorchoice = if atty::is(atty::Stream::Stdout) {
-        ColorChoice::Auto
-    } else {
-        ColorChoice::Never
+    // Whatever the default branch of the index is called, its `HEAD` points to it.
+    let refspec = format!("+HEAD:{}", INDEX_HEAD);
+    let fetch = |repo: &git2::Repository| {
+        if git_fetch_with_cli {
+            fetch_with_cli(repo, registry.as_str(), &refspec)
+        } else {
+            fetch_with_git2(repo, registry.as_str(), &refspec, token)
+        }
+        .chain_err(|| ErrorKind::IndexFetchFailed(registry.to_string()))
     };
-    let mut output = StandardStream::stdout(colorchoice);
 
     if !registry_path.as_path().exists() {
-        output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-        write!(output, "{:>12}", "Initializing")?;
-        output.reset()?;
-        writeln!(output, " '{}' index", registry)?;
+        print_status(
+            "Initializing",
+            Color::Green,
+            &format!("'{}' index", registry),
+        )?;
 
         let mut opts = git2::RepositoryInitOptions::new();
         opts.bare(true);
-        git2::Repository::init_opts(&registry_path, &opts)?;
+        let repo = git2::Repository::init_opts(&registry_path, &opts)?;
+        let fetched = fetch(&repo).and_then(|()| {
+            repo.find_reference(INDEX_HEAD)
+                .chain_err(|| ErrorKind::IndexFetchFailed(registry.to_string()))
+        });
+
+        // Don't leave an empty index behind, it would not be fetched into again.
+        if let Err(err) = fetched {
+            let _ = fs::remove_dir_all(&registry_path);
+            return Err(err);
+        }
         return Ok(());
     }
 
     let repo = git2::Repository::open(&registry_path)?;
-    output.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-    write!(output, "{:>12}", "Updating")?;
+    print_status("Updating", Color::Green, &format!("'{}' index", registry))?;
+
+    fetch(&repo)
+}
+
+/// Lock the package cache of the cargo home directory, like cargo does while it downloads, waiting
+/// for any other process holding the lock. The lock is released when the file is dropped.
+pub(crate) fn lock_package_cache() -> Result<fs::File> {
+    let cargo_home = cargo_home()?;
+    fs::create_dir_all(&cargo_home)?;
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(cargo_home.join(".package-cache"))?;
+    if file.try_lock_exclusive().is_err() {
+        print_status(
+            "Blocking",
+            Color::Cyan,
+            "waiting for file lock on package cache",
+        )?;
+        file.lock_exclusive()?;
+    }
+    Ok(file)
+}
+
+/// Print a message to stderr like cargo does, after a colored status word.
+fn print_status(status: &str, color: Color, message: &str) -> Result<()> {
+    let colorchoice = if atty::is(atty::Stream::Stderr) {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+    let mut output = StandardStream::stderr(colorchoice);
+    output.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+    write!(output, "{:>12}", status)?;
     output.reset()?;
-    writeln!(output, " '{}' index", registry)?;
+    writeln!(output, " {}", message)?;
+    Ok(())
+}
 
-    let refspec = "refs/heads/master:refs/remotes/origin/master";
-    fetch_with_cli(&repo, registry.as_str(), refspec)?;
+// ref: https://github.com/rust-lang/cargo/blob/57986eac7157261c33f0123bade7ccd20f15200f/src/cargo/sources/git/utils.rs#L680
+fn fetch_with_git2(
+    repo: &git2::Repository,
+    url: &str,
+    refspec: &str,
+    token: Option<&str>,
+) -> Result<()> {
+    let git_config = git2::Config::open_default()?;
+    // Each kind of credentials is only tried once, as libgit2 asks again as long as they fail.
+    let (mut tried_ssh_agent, mut tried_token, mut tried_helper, mut tried_default) =
+        (false, false, false, false);
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed| {
+        if allowed.contains(git2::CredentialType::USERNAME) {
+            return git2::Cred::username(username.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::SSH_KEY) && !tried_ssh_agent {
+            tried_ssh_agent = true;
+            return git2::Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) && !tried_token {
+            tried_token = true;
+            if let Some(token) = token {
+                return git2::Cred::userpass_plaintext(username.unwrap_or("token"), token);
+            }
+        }
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) && !tried_helper {
+            tried_helper = true;
+            return git2::Cred::credential_helper(&git_config, url, username);
+        }
+        if allowed.contains(git2::CredentialType::DEFAULT) && !tried_default {
+            tried_default = true;
+            return git2::Cred::default();
+        }
+        Err(git2::Error::from_str("no authentication method succeeded"))
+    });
+
+    let mut proxy = git2::ProxyOptions::new();
+    match env_proxy::for_url_str(url).to_string() {
+        Some(proxy_url) => proxy.url(&proxy_url),
+        // Use the proxy configured for git, if any.
+        None => proxy.auto(),
+    };
 
+    let mut opts = git2::FetchOptions::new();
+    opts.remote_callbacks(callbacks)
+        .proxy_options(proxy)
+        .download_tags(git2::AutotagOption::None);
+    repo.remote_anonymous(url)?
+        .fetch(&[refspec], Some(&mut opts), None)?;
     Ok(())
 }
 
 // https://github.com/rust-lang/cargo/blob/57986eac7157261c33f0123bade7ccd20f15200f/src/cargo/sources/git/utils.rs#L758
 fn fetch_with_cli(repo: &git2::Repository, url: &str, refspec: &str) -> Result<()> {
-    let cmd = subprocess::Exec::shell("git")
+    let cmd = subprocess::Exec::cmd("git")
         .arg("fetch")
-        .arg("--tags") // fetch all tags
+        .arg("--no-tags") // tags are not needed
         .arg("--force") // handle force pushes
         .arg("--update-head-ok") // see discussion in rust-lang/cargo#2078
         .arg(url)
@@ -145,14 +285,23 @@ fn fetch_with_cli(repo: &git2::Repository, url: &str, refspec: &str) -> Result<(
         .env_remove("GIT_INDEX_FILE")
         .env_remove("GIT_OBJECT_DIRECTORY")
         .env_remove("GIT_ALTERNATE_OBJECT_DIRECTORIES")
+        .stderr(subprocess::Redirection::Pipe)
         .cwd(repo.path());
 
-    let _ = cmd.capture().map_err(|e| match e {
+    let capture = cmd.capture().map_err(|e| match e {
         subprocess::PopenError::IoError(io) => ErrorKind::Io(io),
         subprocess::PopenError::LogicError(_) | subprocess::PopenError::Utf8Error(_) => {
             unreachable!("expected only io error")
         }
     })?;
+    if !capture.success() {
+        return Err(format!(
+            "`git fetch` failed with {:?}: {}",
+            capture.exit_status,
+            capture.stderr_str().trim()
+        )
+        .into());
+    }
     Ok(())
 }
 
@@ -261,14 +410,9 @@ fn get_no_latest_version_from_json_when_all_are_yanked() {
 /// Fuzzy query crate from registry index
 fn fuzzy_query_registry_index(
     crate_name: impl Into<String>,
-    registry_path: impl AsRef<Path>,
+    index: &dyn RegistryIndex,
 ) -> Result<Vec<CrateVersion>> {
     let crate_name = crate_name.into();
-    let repo = git2::Repository::open(registry_path)?;
-    let tree = repo
-        .find_reference("refs/remotes/origin/master")?
-        .peel_to_tree()?;
-
     let mut names = gen_fuzzy_crate_names(crate_name.clone())?;
     if let Some(index) = names.iter().position(|x| *x == crate_name) {
         // ref: https://github.com/killercup/cargo-edit/pull/317#discussion_r307365704
@@ -276,20 +420,9 @@ fn fuzzy_query_registry_index(
     }
 
     for the_name in names {
-        let file = match tree.get_path(&PathBuf::from(summary_raw_path(&the_name))) {
-            Ok(x) => x.to_object(&repo)?.peel_to_blob()?,
-            Err(_) => continue,
-        };
-        let content = String::from_utf8(file.content().to_vec())
-            .map_err(|_| ErrorKind::InvalidSummaryJson)?;
-
-        return content
-            .lines()
-            .map(|line: &str| {
-                serde_json::from_str::<CrateVersion>(line)
-                    .map_err(|_| ErrorKind::InvalidSummaryJson.into())
-            })
-            .collect::<Result<Vec<CrateVersion>>>();
+        if let Some(versions) = index.crate_versions(&the_name)? {
+            return Ok(versions);
+        }
     }
     Err(ErrorKind::NoCrate(crate_name).into())
 }
@@ -350,6 +483,37 @@ pub fn get_crate_name_from_gitlab(repo: &str) -> Result<String> {
     })
 }
 
+/// Query crate name by fetching a git repository and reading its Cargo.toml
+///
+/// Only the commit at `HEAD` is fetched, into a temporary repository which is removed afterwards
+/// (libgit2 cannot fetch shallowly). Any URL git understands works, e.g. `ssh://` or `file://`.
+///
+/// The name will be returned as a string. This will fail, when
+///
+/// - the repository cannot be fetched.
+/// - there is no Cargo.toml with a package name at the root of the repository.
+pub fn get_crate_name_from_git(repo: &str) -> Result<String> {
+    let dir =
+        TempDir::new("cargo-edit-git").chain_err(|| "Failed to create temporary directory")?;
+    let mut opts = git2::RepositoryInitOptions::new();
+    opts.bare(true);
+    git2::Repository::init_opts(dir.path(), &opts)
+        .map_err(Error::from)
+        .and_then(|git_repo| {
+            fetch_with_git2(&git_repo, repo, &format!("+HEAD:{}", INDEX_HEAD), None)?;
+            let tree = git_repo.find_reference(INDEX_HEAD)?.peel_to_tree()?;
+            let blob = tree
+                .get_path(Path::new("Cargo.toml"))?
+                .to_object(&git_repo)?
+                .peel_to_blob()?;
+            let manifest = String::from_utf8_lossy(blob.content())
+                .parse::<Manifest>()
+                .chain_err(|| ErrorKind::ParseCargoToml)?;
+            get_name_from_manifest(&manifest)
+        })
+        .chain_err(|| format!("Failed to fetch crate from git repository `{}`", repo))
+}
+
 /// Query crate name by accessing Cargo.toml in a local path
 ///
 /// The name will be returned as a string. This will fail, when
@@ -374,15 +538,22 @@ const fn get_default_timeout() -> Duration {
     Duration::from_secs(10)
 }
 
-fn get_with_timeout(url: &str, timeout: Duration) -> reqwest::Result<reqwest::Response> {
-    let client = reqwest::ClientBuilder::new()
+/// Build an HTTP client honouring the proxy settings of the environment.
+pub(crate) fn http_client() -> Result<reqwest::Client> {
+    build_client(get_default_timeout()).chain_err(|| "Failed to set up HTTP client")
+}
+
+fn build_client(timeout: Duration) -> reqwest::Result<reqwest::Client> {
+    reqwest::ClientBuilder::new()
         .timeout(timeout)
         .proxy(reqwest::Proxy::custom(|url| {
             env_proxy::for_url(url).to_url()
         }))
-        .build()?;
+        .build()
+}
 
-    client
+fn get_with_timeout(url: &str, timeout: Duration) -> reqwest::Result<reqwest::Response> {
+    build_client(timeout)?
         .get(url)
         .send()
         .and_then(reqwest::Response::error_for_status)
@@ -457,22 +628,3 @@ fn test_gen_fuzzy_crate_names() {
         &["DC__janus", "DC_-janus", "DC-_janus", "DC--janus"],
     );
 }
-
-fn summary_raw_path(crate_name: &str) -> String {
-    match crate_name.len() {
-        0 => unreachable!("we check that crate_name is not empty here"),
-        1 => format!("1/{}", crate_name),
-        2 => format!("2/{}", crate_name),
-        3 => format!("3/{}/{}", &crate_name[..1], crate_name),
-        _ => format!("{}/{}/{}", &crate_name[..2], &crate_name[2..4], crate_name),
-    }
-}
-
-#[test]
-fn test_summary_raw_path() {
-    assert_eq!(summary_raw_path("a"), "1/a");
-    assert_eq!(summary_raw_path("ab"), "2/ab");
-    assert_eq!(summary_raw_path("abc"), "3/a/abc");
-    assert_eq!(summary_raw_path("abcd"), "ab/cd/abcd");
-    assert_eq!(summary_raw_path("abcdefg"), "ab/cd/abcdefg");
-}
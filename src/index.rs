@@ -0,0 +1,412 @@
+//! Sources of the versions published for a crate
+//!
+//! The versions of a crate are looked up in a [`RegistryIndex`]. Git and sparse indices are what
+//! cargo itself uses, a local index reads the same summary files from a directory (e.g. of a
+//! `local-registry` source), a directory index reads the crates vendored into a `directory`
+//! source, and a [`MemoryIndex`] holds versions given by the caller.
+
+use crate::config::{CargoConfig, Token};
+use crate::errors::*;
+use crate::fetch::{fetch_registry_index, lock_package_cache, INDEX_HEAD, LEGACY_INDEX_HEAD};
+use crate::registry::{
+    local_source, registry_cache_path_from_url, registry_path_from_url, LocalSource,
+};
+use crate::sparse::{is_sparse, SparseIndex};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+/// A version of a crate, as listed in a registry index
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct CrateVersion {
+    /// The name of the crate, as it was published
+    pub name: String,
+    /// The version published
+    #[serde(rename = "vers")]
+    pub version: semver::Version,
+    /// Whether the version has been yanked
+    pub yanked: bool,
+}
+
+/// A source of the versions published for a crate
+pub trait RegistryIndex: fmt::Debug {
+    /// Get all versions of the crate named exactly `crate_name`, or `None` if the index does not
+    /// know the crate.
+    fn crate_versions(&self, crate_name: &str) -> Result<Option<Vec<CrateVersion>>>;
+
+    /// Bring the index up to date with the registry. Indices that are queried on demand have
+    /// nothing to do here.
+    fn update(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the index is used without accessing the network, in which case versions already
+    /// downloaded are preferred.
+    fn is_offline(&self) -> bool {
+        false
+    }
+
+    /// Whether the `.crate` file of `version` has been downloaded to the crate cache.
+    fn is_downloaded(&self, _version: &CrateVersion) -> bool {
+        false
+    }
+}
+
+/// Open the index of a registry, according to the protocol of its URL, with the settings of
+/// `config`. Offline, the index is read from what has been downloaded before.
+pub fn registry_index(registry: &Url, config: &CargoConfig) -> Result<Box<dyn RegistryIndex>> {
+    if let Some(source) = local_source(registry) {
+        return Ok(match source {
+            LocalSource::Registry(path) => Box::new(LocalIndex::new(path.join("index"))),
+            LocalSource::Directory(path) => Box::new(DirectoryIndex::new(path)),
+        });
+    }
+    if is_sparse(registry) {
+        let cache_path = registry_path_from_url(registry)?;
+        let mut index = SparseIndex::new(registry, cache_path)?;
+        if let Some(token) = config.registry_token(registry) {
+            index = index.token(token.secret().to_owned());
+        }
+        if config.offline() {
+            index = index.offline(registry_cache_path_from_url(registry)?);
+        }
+        Ok(Box::new(index))
+    } else {
+        Ok(Box::new(GitIndex::new(registry, config)?))
+    }
+}
+
+/// Parse a summary file of an index, which lists one version per line.
+pub(crate) fn parse_summary(content: &str) -> Result<Vec<CrateVersion>> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str::<CrateVersion>(line)
+                .map_err(|_| ErrorKind::InvalidSummaryJson.into())
+        })
+        .collect()
+}
+
+/// Check whether the `.crate` file of `version` is in the crate cache at `crate_cache`.
+pub(crate) fn is_cached_crate(crate_cache: &Path, version: &CrateVersion) -> bool {
+    crate_cache
+        .join(format!("{}-{}.crate", version.name, version.version))
+        .is_file()
+}
+
+/// Get the path of the summary file of a crate, relative to the root of an index.
+pub(crate) fn summary_raw_path(crate_name: &str) -> String {
+    match crate_name.len() {
+        0 => unreachable!("we check that crate_name is not empty here"),
+        1 => format!("1/{}", crate_name),
+        2 => format!("2/{}", crate_name),
+        3 => format!("3/{}/{}", &crate_name[..1], crate_name),
+        _ => format!("{}/{}/{}", &crate_name[..2], &crate_name[2..4], crate_name),
+    }
+}
+
+/// The git index of a registry, as cloned into the cargo home directory
+#[derive(Debug)]
+pub struct GitIndex {
+    url: Url,
+    path: PathBuf,
+    git_fetch_with_cli: bool,
+    offline: bool,
+    /// How long a fetch is good for, or `None` if the index is only fetched when missing
+    freshness: Option<Duration>,
+    token: Option<Token>,
+    /// The directory the `.crate` files of the registry are downloaded to
+    crate_cache: PathBuf,
+}
+
+impl GitIndex {
+    /// Use the clone of the index at `registry` found in the cargo home directory, fetching it as
+    /// set in `config`.
+    pub fn new(registry: &Url, config: &CargoConfig) -> Result<Self> {
+        Ok(GitIndex {
+            url: registry.clone(),
+            path: registry_path_from_url(registry)?,
+            git_fetch_with_cli: config.git_fetch_with_cli(),
+            offline: config.offline(),
+            freshness: config.index_freshness(),
+            token: config.registry_token(registry),
+            crate_cache: registry_cache_path_from_url(registry)?,
+        })
+    }
+
+    /// Open the clone of the index, failing if it has not been fetched yet.
+    fn open(&self) -> Result<(git2::Repository, git2::Oid)> {
+        let open = || -> Result<_> {
+            let repo = git2::Repository::open(&self.path)?;
+            let head = repo
+                .find_reference(INDEX_HEAD)
+                .or_else(|_| repo.find_reference(LEGACY_INDEX_HEAD))?
+                .peel_to_tree()?
+                .id();
+            Ok((repo, head))
+        };
+        open().chain_err(|| ErrorKind::IndexNotDownloaded(self.url.to_string()))
+    }
+
+    /// Check whether the index has been fetched recently enough not to be fetched again, going by
+    /// the modification time of `FETCH_HEAD`, which both git and cargo write.
+    fn is_fresh(&self) -> bool {
+        if self.open().is_err() {
+            return false;
+        }
+        let freshness = match self.freshness {
+            Some(freshness) => freshness,
+            None => return true,
+        };
+        fs::metadata(self.path.join("FETCH_HEAD"))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|fetched| fetched.elapsed().ok())
+            .map_or(false, |elapsed| elapsed < freshness)
+    }
+}
+
+impl RegistryIndex for GitIndex {
+    fn crate_versions(&self, crate_name: &str) -> Result<Option<Vec<CrateVersion>>> {
+        let (repo, head) = self.open()?;
+        let tree = repo.find_tree(head)?;
+        let file = match tree.get_path(Path::new(&summary_raw_path(crate_name))) {
+            Ok(x) => x.to_object(&repo)?.peel_to_blob()?,
+            Err(_) => return Ok(None),
+        };
+        let content = String::from_utf8(file.content().to_vec())
+            .map_err(|_| ErrorKind::InvalidSummaryJson)?;
+        parse_summary(&content).map(Some)
+    }
+
+    fn update(&self) -> Result<()> {
+        if self.offline {
+            // A missing index is reported when it is read, as it may not be needed at all.
+            return Ok(());
+        }
+        // Check freshness under the lock, in case another process is fetching the index.
+        let _lock = lock_package_cache()?;
+        if self.is_fresh() {
+            return Ok(());
+        }
+        let token = self.token.as_ref().map(Token::secret);
+        fetch_registry_index(&self.url, self.git_fetch_with_cli, token)
+    }
+
+    fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    fn is_downloaded(&self, version: &CrateVersion) -> bool {
+        is_cached_crate(&self.crate_cache, version)
+    }
+}
+
+/// An index laid out like a git index, read from a local directory
+#[derive(Debug)]
+pub struct LocalIndex {
+    path: PathBuf,
+}
+
+impl LocalIndex {
+    /// Read the index in the directory at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LocalIndex { path: path.into() }
+    }
+}
+
+impl RegistryIndex for LocalIndex {
+    fn crate_versions(&self, crate_name: &str) -> Result<Option<Vec<CrateVersion>>> {
+        let summary = self.path.join(summary_raw_path(&crate_name.to_lowercase()));
+        if !summary.is_file() {
+            return Ok(None);
+        }
+        parse_summary(&fs::read_to_string(summary)?).map(Some)
+    }
+}
+
+/// An index of the crates vendored into a directory, e.g. by `cargo vendor`
+///
+/// Every subdirectory holding a `Cargo.toml` and a `.cargo-checksum.json` is a crate. The
+/// directory is read once, on the first lookup.
+#[derive(Debug)]
+pub struct DirectoryIndex {
+    path: PathBuf,
+    /// The vendored versions by crate name, once read
+    crates: RefCell<Option<HashMap<String, Vec<CrateVersion>>>>,
+}
+
+/// The part of a vendored crate's manifest naming it
+#[derive(Deserialize)]
+struct VendoredManifest {
+    package: VendoredPackage,
+}
+
+#[derive(Deserialize)]
+struct VendoredPackage {
+    name: String,
+    version: semver::Version,
+}
+
+impl DirectoryIndex {
+    /// Read the crates vendored into the directory at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        DirectoryIndex {
+            path: path.into(),
+            crates: RefCell::new(None),
+        }
+    }
+
+    /// Read the name and version of every vendored crate. Crates whose manifest cannot be
+    /// parsed are skipped with a warning.
+    fn read_crates(&self) -> Result<HashMap<String, Vec<CrateVersion>>> {
+        let mut crates = HashMap::<_, Vec<_>>::new();
+        for entry in fs::read_dir(&self.path)? {
+            let dir = entry?.path();
+            if !dir.join(".cargo-checksum.json").is_file() {
+                continue;
+            }
+            let manifest = match fs::read(dir.join("Cargo.toml")) {
+                Ok(manifest) => manifest,
+                Err(_) => continue,
+            };
+            let package = match toml::from_slice::<VendoredManifest>(&manifest) {
+                Ok(manifest) => manifest.package,
+                Err(e) => {
+                    eprintln!(
+                        "WARN: Skipping the crate vendored in `{}`: {}",
+                        dir.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            crates
+                .entry(package.name.clone())
+                .or_default()
+                .push(CrateVersion {
+                    name: package.name,
+                    version: package.version,
+                    yanked: false,
+                });
+        }
+        Ok(crates)
+    }
+}
+
+impl RegistryIndex for DirectoryIndex {
+    fn crate_versions(&self, crate_name: &str) -> Result<Option<Vec<CrateVersion>>> {
+        let mut crates = self.crates.borrow_mut();
+        if crates.is_none() {
+            *crates = Some(self.read_crates()?);
+        }
+        Ok(crates
+            .as_ref()
+            .and_then(|crates| crates.get(crate_name))
+            .cloned())
+    }
+}
+
+/// An index holding versions given by the caller, e.g. for tests
+#[derive(Debug, Default)]
+pub struct MemoryIndex {
+    crates: HashMap<String, Vec<CrateVersion>>,
+}
+
+impl MemoryIndex {
+    /// Create an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a version of a crate to the index.
+    pub fn insert(&mut self, version: CrateVersion) {
+        self.crates
+            .entry(version.name.clone())
+            .or_default()
+            .push(version);
+    }
+}
+
+impl RegistryIndex for MemoryIndex {
+    fn crate_versions(&self, crate_name: &str) -> Result<Option<Vec<CrateVersion>>> {
+        Ok(self.crates.get(crate_name).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_raw_path() {
+        assert_eq!(summary_raw_path("a"), "1/a");
+        assert_eq!(summary_raw_path("ab"), "2/ab");
+        assert_eq!(summary_raw_path("abc"), "3/a/abc");
+        assert_eq!(summary_raw_path("abcd"), "ab/cd/abcd");
+        assert_eq!(summary_raw_path("abcdefg"), "ab/cd/abcdefg");
+    }
+
+    #[test]
+    fn parse_summary_lines() {
+        let versions = parse_summary(
+            "{\"name\":\"foo\",\"vers\":\"0.1.0\",\"yanked\":false,\"deps\":[]}\n\
+             {\"name\":\"foo\",\"vers\":\"0.2.0\",\"yanked\":true,\"deps\":[]}\n",
+        )
+        .unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(
+            versions[1].version,
+            semver::Version::parse("0.2.0").unwrap()
+        );
+        assert!(versions[1].yanked);
+
+        assert!(parse_summary("{\"name\":\"foo\"}").is_err());
+    }
+
+    #[test]
+    fn directory_index_skips_invalid_crates() {
+        let dir = tempdir::TempDir::new("cargo-edit-vendor").unwrap();
+        let vendor = |name: &str, manifest: &str| {
+            let path = dir.path().join(name);
+            fs::create_dir(&path).unwrap();
+            fs::write(path.join("Cargo.toml"), manifest).unwrap();
+            fs::write(path.join(".cargo-checksum.json"), "{\"files\":{}}").unwrap();
+        };
+        vendor("foo", "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n");
+        vendor(
+            "foo-0.2.0",
+            "[package]\nname = \"foo\"\nversion = \"0.2.0\"\n",
+        );
+        vendor("invalid", "[package]\nname = \"invalid\"\n");
+
+        let index = DirectoryIndex::new(dir.path());
+        let mut versions = index.crate_versions("foo").unwrap().unwrap();
+        versions.sort_by(|a, b| a.version.cmp(&b.version));
+        assert_eq!(
+            versions
+                .iter()
+                .map(|v| v.version.to_string())
+                .collect::<Vec<_>>(),
+            vec!["0.1.0", "0.2.0"]
+        );
+        assert!(index.crate_versions("invalid").unwrap().is_none());
+
+        // The directory is only read once.
+        fs::remove_dir_all(dir.path().join("foo")).unwrap();
+        assert_eq!(index.crate_versions("foo").unwrap().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn local_index_reads_summary_files() {
+        let index = LocalIndex::new("tests/fixtures/registry/index");
+        let versions = index.crate_versions("docopt").unwrap().unwrap();
+        assert!(versions.iter().all(|v| v.name == "docopt"));
+        assert!(index.crate_versions("no-such-crate").unwrap().is_none());
+    }
+}
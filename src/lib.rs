@@ -18,19 +18,31 @@ extern crate error_chain;
 #[macro_use]
 extern crate serde_derive;
 
+mod config;
 mod crate_name;
 mod dependency;
 mod errors;
 mod fetch;
+mod index;
 mod manifest;
 mod registry;
+mod sparse;
 
+pub use crate::config::CargoConfig;
 pub use crate::crate_name::CrateName;
 pub use crate::dependency::Dependency;
 pub use crate::errors::*;
 pub use crate::fetch::{
-    get_crate_name_from_github, get_crate_name_from_gitlab, get_crate_name_from_path,
-    get_latest_dependency, update_registry_index,
+    get_compatible_dependency, get_crate_name_from_git, get_crate_name_from_github,
+    get_crate_name_from_gitlab, get_crate_name_from_path, get_latest_dependency,
+    update_registry_index,
 };
-pub use crate::manifest::{find, LocalManifest, Manifest};
-pub use crate::registry::registry_url;
+pub use crate::index::{
+    registry_index, CrateVersion, DirectoryIndex, GitIndex, LocalIndex, MemoryIndex, RegistryIndex,
+};
+pub use crate::manifest::{
+    find, find_workspace_root, DependencyKind, FeatureCleanup, LocalManifest, Manifest,
+    ManifestDependency, UpgradeKind, VersionUpgrade,
+};
+pub use crate::registry::{registry_url, GitReference};
+pub use crate::sparse::SparseIndex;
@@ -19,6 +19,104 @@ pub struct Manifest {
     pub data: toml_edit::Document,
 }
 
+/// The kind of a dependency, following the section it is declared in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    /// A dependency from `dependencies`
+    Normal,
+    /// A dependency from `dev-dependencies`
+    Development,
+    /// A dependency from `build-dependencies`
+    Build,
+}
+
+impl DependencyKind {
+    /// Get the kind of dependencies a table with the given name contains.
+    pub fn from_table_name(table: &str) -> Option<DependencyKind> {
+        match table {
+            "dependencies" => Some(DependencyKind::Normal),
+            "dev-dependencies" => Some(DependencyKind::Development),
+            "build-dependencies" => Some(DependencyKind::Build),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the table containing dependencies of this kind.
+    pub fn table_name(self) -> &'static str {
+        match self {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Development => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+        }
+    }
+}
+
+/// A dependency as it is declared in a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestDependency {
+    /// The kind of section the dependency is declared in
+    pub kind: DependencyKind,
+    /// The target the section is specific to, e.g. `cfg(unix)`, if any
+    pub target: Option<String>,
+    /// The key of the dependency in the manifest, which differs from the package name if the
+    /// dependency is renamed
+    pub key: String,
+    /// The parsed dependency
+    pub dependency: Dependency,
+}
+
+impl ManifestDependency {
+    /// Get the name of the package this dependency refers to
+    pub fn name(&self) -> &str {
+        &self.dependency.name
+    }
+
+    /// Get the path of the table the dependency is declared in
+    pub fn table_path(&self) -> Vec<String> {
+        match self.target {
+            Some(ref target) => vec![
+                "target".to_owned(),
+                target.clone(),
+                self.kind.table_name().to_owned(),
+            ],
+            None => vec![self.kind.table_name().to_owned()],
+        }
+    }
+}
+
+/// A change made to the `[features]` table after a dependency was removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeatureCleanup {
+    /// A member referring to the removed dependency was removed from a feature
+    RemovedMember {
+        /// The feature the member was removed from
+        feature: String,
+        /// The removed member, e.g. `dep:serde` or `serde/derive`
+        member: String,
+    },
+    /// A feature was removed because it no longer enables anything
+    RemovedFeature(String),
+}
+
+/// Check whether a feature member refers to the dependency `dep` or to a removed feature.
+///
+/// A plain member naming `dep` refers to the feature of that name if there is one (`shadowed`),
+/// and to the implicit feature of the optional dependency otherwise.
+fn refers_to_dependency(
+    member: &str,
+    dep: &str,
+    removed_features: &[String],
+    shadowed: bool,
+) -> bool {
+    if let Some(name) = member.strip_prefix("dep:") {
+        name == dep
+    } else if let Some(index) = member.find('/') {
+        member[..index].trim_end_matches('?') == dep
+    } else {
+        removed_features.iter().any(|f| f == member) || (!shadowed && member == dep)
+    }
+}
+
 /// If a manifest is specified, return that one, otherise perform a manifest search starting from
 /// the current directory.
 /// If a manifest is specified, return that one. If a path is specified, perform a manifest search
@@ -51,6 +149,39 @@ fn search(dir: &Path) -> Result<PathBuf> {
     }
 }
 
+/// Find the manifest of the workspace root for the package whose manifest is at `manifest_path`.
+///
+/// This is the manifest itself if it has a `[workspace]` table, the one `package.workspace` points
+/// to if set, and otherwise the closest manifest with a `[workspace]` table in a parent directory.
+/// The returned path is canonical.
+pub fn find_workspace_root(manifest_path: &Path) -> Result<PathBuf> {
+    let manifest_path =
+        fs::canonicalize(manifest_path).chain_err(|| "Failed to resolve manifest path")?;
+    let manifest = Manifest::open(&Some(manifest_path.clone()))?;
+    if !manifest.data["workspace"].is_none() {
+        return Ok(manifest_path);
+    }
+
+    let package_dir = manifest_path
+        .parent()
+        .expect("a manifest path always has a parent");
+    if let Some(root) = manifest.data["package"]["workspace"].as_str() {
+        return fs::canonicalize(package_dir.join(root).join(MANIFEST_FILENAME))
+            .chain_err(|| format!("Failed to find the workspace root at `{}`", root));
+    }
+
+    for dir in package_dir.ancestors().skip(1) {
+        let candidate = dir.join(MANIFEST_FILENAME);
+        if candidate.is_file()
+            && !Manifest::open(&Some(candidate.clone()))?.data["workspace"].is_none()
+        {
+            return Ok(candidate);
+        }
+    }
+
+    Err(ErrorKind::MissingWorkspaceRoot(manifest_path.display().to_string()).into())
+}
+
 fn merge_inline_table(old_dep: &mut toml_edit::Item, new: &toml_edit::Item) {
     for (k, v) in new
         .as_inline_table()
@@ -64,18 +195,52 @@ fn merge_inline_table(old_dep: &mut toml_edit::Item, new: &toml_edit::Item) {
 fn str_or_1_len_table(item: &toml_edit::Item) -> bool {
     item.is_str() || item.as_table_like().map(|t| t.len() == 1).unwrap_or(false)
 }
+
+/// Get the features listed in a dependency entry, if any.
+fn get_features(dep: &toml_edit::Item) -> Vec<String> {
+    dep.as_table_like()
+        .and_then(|t| t.get("features"))
+        .and_then(toml_edit::Item::as_array)
+        .into_iter()
+        .flat_map(toml_edit::Array::iter)
+        .filter_map(toml_edit::Value::as_str)
+        .map(String::from)
+        .collect()
+}
+
 /// Merge a new dependency into an old entry. See `Dependency::to_toml` for what the format of the
 /// new dependency will be.
+///
+/// Features of the new dependency are added to the ones already enabled on the old entry.
 fn merge_dependencies(old_dep: &mut toml_edit::Item, new: &Dependency) {
     assert!(!old_dep.is_none());
 
     let new_toml = new.to_toml().1;
+    let old_features = get_features(old_dep);
 
     if str_or_1_len_table(old_dep) {
         // The old dependency is just a version/git/path. We are safe to overwrite.
         *old_dep = new_toml;
     } else if old_dep.is_table_like() {
-        for key in &["version", "path", "git"] {
+        let mut stale_keys = vec![
+            "version",
+            "path",
+            "git",
+            "branch",
+            "tag",
+            "rev",
+            "workspace",
+        ];
+        if new.inherited() {
+            // these can only be set in the workspace root
+            stale_keys.extend(&[
+                "registry",
+                "package",
+                "default-features",
+                "default_features",
+            ]);
+        }
+        for key in stale_keys {
             // remove this key/value pairs
             old_dep[key] = toml_edit::Item::None;
         }
@@ -88,21 +253,197 @@ fn merge_dependencies(old_dep: &mut toml_edit::Item, new: &Dependency) {
         unreachable!("Invalid old dependency type");
     }
 
+    if let Some(new_features) = new.features() {
+        let mut features = old_features;
+        for feature in new_features {
+            if !features.contains(feature) {
+                features.push(feature.clone());
+            }
+        }
+        old_dep["features"] = toml_edit::value(
+            features
+                .iter()
+                .map(String::as_str)
+                .collect::<toml_edit::Value>(),
+        );
+    }
+
     if let Some(t) = old_dep.as_inline_table_mut() {
         t.fmt()
     }
 }
 
-/// Print a message if the new dependency version is different from the old one.
-fn print_upgrade_if_necessary(
-    crate_name: &str,
-    old_dep: &toml_edit::Item,
-    new_version: &toml_edit::Item,
-) -> Result<()> {
-    let old_version = if str_or_1_len_table(old_dep) {
-        old_dep.clone()
-    } else if old_dep.is_table_like() {
-        let version = old_dep["version"].clone();
+/// How far apart two versions are in semver terms, given by the leftmost component that changed.
+///
+/// As in cargo, the first non-zero component of the old version counts as its major version, so
+/// `0.2` to `0.3` is a `Major` upgrade and `0.2.1` to `0.2.5` a `Minor` one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpgradeKind {
+    /// A breaking change: the major version changed.
+    Major,
+    /// The minor version changed.
+    Minor,
+    /// Only the patch version (or the pre-release) changed.
+    Patch,
+}
+
+impl UpgradeKind {
+    /// Classify the upgrade from a version requirement, e.g. `^0.8` or `=1.2.3`, to a version.
+    ///
+    /// The requirement is compared through the lowest version it matches, so `1.2` to `1.2.5` is
+    /// a `Patch` upgrade. Returns `None` if either side can't be parsed, or they don't differ.
+    ///
+    /// ```
+    /// use cargo_edit::UpgradeKind;
+    ///
+    /// assert_eq!(UpgradeKind::between("0.8", "1.1.0"), Some(UpgradeKind::Major));
+    /// assert_eq!(UpgradeKind::between("^0.2.28", "0.3.0"), Some(UpgradeKind::Major));
+    /// assert_eq!(UpgradeKind::between("^0.2.28", "0.2.65"), Some(UpgradeKind::Minor));
+    /// assert_eq!(UpgradeKind::between("1.2", "1.2.5"), Some(UpgradeKind::Patch));
+    /// assert_eq!(UpgradeKind::between("1.2.5", "1.2.5"), None);
+    /// ```
+    pub fn between(old_requirement: &str, new_version: &str) -> Option<UpgradeKind> {
+        let (major, minor, patch) = lowest_version(old_requirement)?;
+        let old = [major, minor, patch];
+        let new = semver::Version::parse(new_version).ok()?;
+        let new_parts = [new.major, new.minor, new.patch];
+        // The index of the component acting as the major version.
+        let major_index = old.iter().position(|&part| part != 0).unwrap_or(2);
+        match old.iter().zip(&new_parts).position(|(old, new)| old != new) {
+            Some(changed) if changed <= major_index => Some(UpgradeKind::Major),
+            Some(changed) if changed == major_index + 1 => Some(UpgradeKind::Minor),
+            Some(_) => Some(UpgradeKind::Patch),
+            None if old_requirement.contains('-') || new.is_prerelease() => {
+                Some(UpgradeKind::Patch)
+            }
+            None => None,
+        }
+    }
+}
+
+impl std::fmt::Display for UpgradeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpgradeKind::Major => write!(f, "major"),
+            UpgradeKind::Minor => write!(f, "minor"),
+            UpgradeKind::Patch => write!(f, "patch"),
+        }
+    }
+}
+
+/// Get the lowest `major.minor.patch` matched by the first comparator of a version requirement.
+/// Missing components count as `0`, and wildcards end the version.
+fn lowest_version(requirement: &str) -> Option<(u64, u64, u64)> {
+    let comparator = requirement.split(',').next()?.trim();
+    let version = comparator
+        .trim_start_matches(|c| "^~=<>".contains(c))
+        .trim()
+        .split(&['-', '+'][..])
+        .next()?;
+    let mut parts = version
+        .split('.')
+        .take_while(|part| !["*", "x", "X"].contains(part))
+        .map(str::parse::<u64>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+/// Get the number of components of the version in a comparator, e.g. 2 for `~1.2`, or `None` for
+/// wildcards.
+fn precision(version: &str) -> Option<usize> {
+    let version = version.split(&['-', '+'][..]).next()?;
+    let parts = version.split('.').collect::<Vec<_>>();
+    if parts.len() > 3 || parts.iter().any(|part| part.parse::<u64>().is_err()) {
+        return None;
+    }
+    Some(parts.len())
+}
+
+/// Format the first `precision` components of a version, or all of it for a pre-release.
+fn format_version(version: &semver::Version, precision: usize) -> String {
+    if version.is_prerelease() {
+        return version.to_string();
+    }
+    [version.major, version.minor, version.patch][..precision.min(3)]
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Rewrite a version requirement to require `version`, keeping its operator and the number of
+/// components it gives, e.g. `~1.2` becomes `~1.4` for `1.4.0`.
+///
+/// In a range such as `>=1.0, <3`, only the upper bounds excluding `version` are moved, to the
+/// next incompatible release (`<4` for `3.1.0`). Any other requirement is replaced by `version`.
+fn upgrade_requirement(requirement: &str, version: &str) -> String {
+    let target = match semver::Version::parse(version) {
+        Ok(version) => version,
+        Err(_) => return version.to_owned(),
+    };
+    let comparators = requirement
+        .split(',')
+        .map(|comparator| {
+            let comparator = comparator.trim();
+            let operand = comparator.trim_start_matches(|c| "^~=<>".contains(c));
+            let operator = &comparator[..comparator.len() - operand.len()];
+            (operator, operand.trim())
+        })
+        .collect::<Vec<_>>();
+
+    match comparators.as_slice() {
+        [(operator, operand)] if ["", "^", "~", "=", ">="].contains(operator) => {
+            match precision(operand) {
+                Some(precision) => {
+                    format!("{}{}", operator, format_version(&target, precision))
+                }
+                None => version.to_owned(),
+            }
+        }
+        _ if comparators
+            .iter()
+            .any(|(operator, _)| operator.starts_with('<')) =>
+        {
+            comparators
+                .iter()
+                .map(|&(operator, operand)| {
+                    let comparator = format!("{}{}", operator, operand);
+                    let excludes_version = semver::VersionReq::parse(&comparator)
+                        .map(|req| !req.matches(&target))
+                        .unwrap_or(false);
+                    match (operator, precision(operand)) {
+                        ("<", Some(precision)) if excludes_version => {
+                            let (bound, needed) = if target.major > 0 {
+                                (semver::Version::new(target.major + 1, 0, 0), 1)
+                            } else if target.minor > 0 {
+                                (semver::Version::new(0, target.minor + 1, 0), 2)
+                            } else {
+                                (semver::Version::new(0, 0, target.patch + 1), 3)
+                            };
+                            format!("<{}", format_version(&bound, precision.max(needed)))
+                        }
+                        ("<=", Some(precision)) if excludes_version => {
+                            format!("<={}", format_version(&target, precision))
+                        }
+                        _ => comparator,
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        _ => version.to_owned(),
+    }
+}
+
+/// Get the version requirement of a dependency entry, if it is a string.
+fn entry_version(dep: &toml_edit::Item) -> Result<Option<String>> {
+    let version = if str_or_1_len_table(dep) {
+        dep
+    } else if dep.is_table_like() {
+        let version = &dep["version"];
         if version.is_none() {
             return Err("Missing version field".into());
         }
@@ -110,11 +451,42 @@ fn print_upgrade_if_necessary(
     } else {
         unreachable!("Invalid old dependency type")
     };
+    Ok(version.as_str().map(String::from))
+}
+
+/// A change of a dependency's version requirement in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionUpgrade {
+    /// The name of the upgraded crate.
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    /// The version requirement before the upgrade.
+    #[serde(rename = "from")]
+    pub old_version: String,
+    /// The version requirement after the upgrade.
+    #[serde(rename = "to")]
+    pub new_version: String,
+    /// How far the upgrade goes, if it could be determined.
+    pub kind: Option<UpgradeKind>,
+}
 
-    if let (Some(old_version), Some(new_version)) = (old_version.as_str(), new_version.as_str()) {
+impl VersionUpgrade {
+    /// Compare an entry's old version requirement with its new one, returning `None` if it doesn't
+    /// change. The upgrade is classified by the `target` version the new requirement was made for.
+    fn new(crate_name: &str, old_version: &str, new_version: &str, target: &str) -> Option<Self> {
         if old_version == new_version {
-            return Ok(());
+            return None;
         }
+        Some(VersionUpgrade {
+            crate_name: crate_name.to_owned(),
+            old_version: old_version.to_owned(),
+            new_version: new_version.to_owned(),
+            kind: UpgradeKind::between(old_version, target),
+        })
+    }
+
+    /// Print the upgrade, e.g. `Upgrading docopt v0.8 -> v1.1.0 (major)`.
+    pub fn print(&self) -> Result<()> {
         let bufwtr = BufferWriter::stdout(ColorChoice::Always);
         let mut buffer = bufwtr.buffer();
         buffer
@@ -124,17 +496,20 @@ fn print_upgrade_if_necessary(
         buffer
             .set_color(&ColorSpec::new())
             .chain_err(|| "Failed to clear output colour")?;
-        writeln!(
+        write!(
             &mut buffer,
             "{} v{} -> v{}",
-            crate_name, old_version, new_version,
+            self.crate_name, self.old_version, self.new_version,
         )
         .chain_err(|| "Failed to write upgrade versions")?;
+        if let Some(kind) = self.kind {
+            write!(&mut buffer, " ({})", kind).chain_err(|| "Failed to write upgrade kind")?;
+        }
+        writeln!(&mut buffer).chain_err(|| "Failed to write upgrade message")?;
         bufwtr
             .print(&buffer)
-            .chain_err(|| "Failed to print upgrade message")?;
+            .chain_err(|| "Failed to print upgrade message")
     }
-    Ok(())
 }
 
 impl Manifest {
@@ -227,16 +602,59 @@ impl Manifest {
         sections
     }
 
-    /// Overwrite a file with TOML data.
-    pub fn write_to_file(&self, file: &mut File) -> Result<()> {
-        if self.data["package"].is_none() && self.data["project"].is_none() {
-            if !self.data["workspace"].is_none() {
-                return Err(ErrorKind::UnexpectedRootManifest.into());
+    /// Get all dependencies declared in the manifest, including target-specific ones.
+    ///
+    /// Entries that cannot be parsed into a `Dependency` are returned as errors.
+    pub fn get_dependencies(&self) -> impl Iterator<Item = Result<ManifestDependency>> {
+        let mut dependencies = Vec::new();
+
+        for (table_path, table) in self.get_sections() {
+            let kind = table_path
+                .last()
+                .and_then(|t| DependencyKind::from_table_name(t))
+                .expect("sections are always dependency tables");
+            let target = if table_path.len() == 3 {
+                Some(table_path[1].clone())
             } else {
-                return Err(ErrorKind::InvalidManifest.into());
+                None
+            };
+            let table_like = table.as_table_like().expect("Unexpected non-table");
+            for (key, item) in table_like.iter() {
+                if item.is_none() {
+                    continue;
+                }
+                dependencies.push(Dependency::from_toml(key, item).map(|dependency| {
+                    ManifestDependency {
+                        kind,
+                        target: target.clone(),
+                        key: key.to_owned(),
+                        dependency,
+                    }
+                }));
             }
         }
 
+        dependencies.into_iter()
+    }
+
+    /// Check whether the manifest is the manifest of a workspace without a package of its own.
+    pub fn is_virtual(&self) -> bool {
+        self.data["package"].is_none()
+            && self.data["project"].is_none()
+            && !self.data["workspace"].is_none()
+    }
+
+    /// Overwrite a file with TOML data.
+    ///
+    /// Virtual manifests are written as well, so `[workspace]` tables can be edited.
+    pub fn write_to_file(&self, file: &mut File) -> Result<()> {
+        if self.data["package"].is_none()
+            && self.data["project"].is_none()
+            && self.data["workspace"].is_none()
+        {
+            return Err(ErrorKind::InvalidManifest.into());
+        }
+
         let s = self.data.to_string_in_original_order();
         let new_contents_bytes = s.as_bytes();
 
@@ -297,27 +715,64 @@ impl Manifest {
         dep: &Dependency,
         dry_run: bool,
     ) -> Result<()> {
+        if let Some(upgrade) = self.merge_table_entry(table_path, item_name, dep, false, dry_run)? {
+            if let Err(e) = upgrade.print() {
+                eprintln!("Error while displaying upgrade message, {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge `dep` into an existing entry, returning the change of its version requirement, if
+    /// any. Nothing is written when `dry_run` is set.
+    ///
+    /// If `preserve_requirement` is set, the version of `dep` is taken as the version to require,
+    /// keeping the operator and precision of the entry's requirement, see `upgrade_requirement`.
+    fn merge_table_entry(
+        &mut self,
+        table_path: &[String],
+        item_name: &str,
+        dep: &Dependency,
+        preserve_requirement: bool,
+        dry_run: bool,
+    ) -> Result<Option<VersionUpgrade>> {
         let table = self.get_table(table_path)?;
-        let new_dep = dep.to_toml().1;
 
         // If (and only if) there is an old entry, merge the new one in.
-        if !table[item_name].is_none() {
-            if let Err(e) = print_upgrade_if_necessary(&dep.name, &table[item_name], &new_dep) {
-                eprintln!("Error while displaying upgrade message, {}", e);
+        if table[item_name].is_none() {
+            return Ok(None);
+        }
+        let old_version = entry_version(&table[item_name]).unwrap_or_else(|e| {
+            eprintln!("Error while displaying upgrade message, {}", e);
+            None
+        });
+        let mut dep = dep.clone();
+        let target = dep.version().map(String::from);
+        if let (true, Some(old_version), Some(target)) =
+            (preserve_requirement, &old_version, &target)
+        {
+            dep = dep.set_version(&upgrade_requirement(old_version, target));
+        }
+        let upgrade = match (&old_version, dep.version(), &target) {
+            (Some(old_version), Some(new_version), Some(target)) => {
+                VersionUpgrade::new(&dep.name, old_version, new_version, target)
             }
-            if !dry_run {
-                merge_dependencies(&mut table[item_name], dep);
-                if let Some(t) = table.as_inline_table_mut() {
-                    t.fmt()
-                }
+            _ => None,
+        };
+        if !dry_run {
+            merge_dependencies(&mut table[item_name], &dep);
+            if let Some(t) = table.as_inline_table_mut() {
+                t.fmt()
             }
         }
 
-        Ok(())
+        Ok(upgrade)
     }
 
     /// Remove entry from a Cargo.toml.
     ///
+    /// Tables left empty by the removal (including `target.<cfg>` tables) are removed as well.
+    ///
     /// # Examples
     ///
     /// ```
@@ -326,32 +781,151 @@ impl Manifest {
     ///
     ///   let mut manifest = Manifest { data: toml_edit::Document::new() };
     ///   let dep = Dependency::new("cargo-edit").set_version("0.1.0");
-    ///   let _ = manifest.insert_into_table(&vec!["dependencies".to_owned()], &dep);
-    ///   assert!(manifest.remove_from_table("dependencies", &dep.name).is_ok());
-    ///   assert!(manifest.remove_from_table("dependencies", &dep.name).is_err());
+    ///   let table = vec!["dependencies".to_owned()];
+    ///   let _ = manifest.insert_into_table(&table, &dep);
+    ///   assert!(manifest.remove_from_table(&table, &dep.name).is_ok());
+    ///   assert!(manifest.remove_from_table(&table, &dep.name).is_err());
     ///   assert!(manifest.data["dependencies"].is_none());
     /// ```
-    pub fn remove_from_table(&mut self, table: &str, name: &str) -> Result<()> {
-        if !self.data[table].is_table_like() {
-            return Err(ErrorKind::NonExistentTable(table.into()).into());
-        } else {
-            {
-                let dep = &mut self.data[table][name];
-                if dep.is_none() {
-                    return Err(ErrorKind::NonExistentDependency(name.into(), table.into()).into());
-                }
-                // remove the dependency
-                *dep = toml_edit::Item::None;
-            }
+    pub fn remove_from_table(&mut self, table_path: &[String], name: &str) -> Result<()> {
+        let table = table_path
+            .iter()
+            .fold(&self.data.root, |item, segment| &item[segment]);
+        if !table.is_table_like() {
+            return Err(ErrorKind::NonExistentTable(table_path.join(".")).into());
+        }
+        if table[name].is_none() {
+            return Err(ErrorKind::NonExistentDependency(name.into(), table_path.join(".")).into());
+        }
+
+        // remove the dependency; every table on the path exists, so indexing does not insert
+        let table = table_path
+            .iter()
+            .fold(&mut self.data.root, |item, segment| &mut item[segment]);
+        table[name] = toml_edit::Item::None;
 
-            // remove table if empty
-            if self.data[table].as_table_like().unwrap().is_empty() {
-                self.data[table] = toml_edit::Item::None;
+        // remove tables left empty, innermost first
+        for depth in (1..=table_path.len()).rev() {
+            let (parent_path, key) = table_path[..depth].split_at(depth - 1);
+            let parent = parent_path
+                .iter()
+                .fold(&mut self.data.root, |item, segment| &mut item[segment]);
+            let is_empty = parent[&key[0]]
+                .as_table_like()
+                .map(toml_edit::TableLike::is_empty)
+                .unwrap_or(false);
+            if !is_empty {
+                break;
             }
+            parent[&key[0]] = toml_edit::Item::None;
         }
+
         Ok(())
     }
 
+    /// Remove entry from every table of a Cargo.toml that contains it.
+    ///
+    /// Returns the paths of the tables the entry was removed from, and fails only if no table
+    /// contains the entry.
+    pub fn remove_from_all_tables(&mut self, name: &str) -> Result<Vec<Vec<String>>> {
+        let mut removed = Vec::new();
+        for (table_path, table) in self.get_sections() {
+            if !table[name].is_none() {
+                self.remove_from_table(&table_path, name)?;
+                removed.push(table_path);
+            }
+        }
+
+        if removed.is_empty() {
+            return Err(ErrorKind::NonExistentDependencyInManifest(name.into()).into());
+        }
+        Ok(removed)
+    }
+
+    /// Remove the members of `[features]` that refer to a dependency which was removed.
+    ///
+    /// Features left empty by this are removed too, along with any references to them. Does
+    /// nothing while a dependency named `name` is still declared in some table.
+    pub fn remove_feature_references(&mut self, name: &str) -> Vec<FeatureCleanup> {
+        let mut cleanups = Vec::new();
+
+        let still_declared = self
+            .get_sections()
+            .iter()
+            .any(|(_, table)| !table[name].is_none());
+        let feature_names = match self.data.as_table().get("features") {
+            Some(features) if !still_declared && features.is_table_like() => features
+                .as_table_like()
+                .unwrap()
+                .iter()
+                .filter(|(_, v)| !v.is_none())
+                .map(|(k, _)| k.to_owned())
+                .collect::<Vec<_>>(),
+            _ => return cleanups,
+        };
+        let shadowed = feature_names.iter().any(|f| f == name);
+
+        // Removing a feature can leave other features referring to it, so repeat until nothing
+        // changes anymore.
+        let mut removed_features = Vec::new();
+        loop {
+            let mut changed = false;
+            for feature in &feature_names {
+                if removed_features.contains(feature) {
+                    continue;
+                }
+                let item = &mut self.data["features"][feature.as_str()];
+                let members = match item.as_array_mut() {
+                    Some(members) => members,
+                    None => continue,
+                };
+                let stale = members
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, member)| member.as_str().map(|m| (index, m.to_owned())))
+                    .filter(|(_, member)| {
+                        refers_to_dependency(member, name, &removed_features, shadowed)
+                    })
+                    .collect::<Vec<_>>();
+                if stale.is_empty() {
+                    continue;
+                }
+
+                for (index, _) in stale.iter().rev() {
+                    members.remove(*index);
+                }
+                members.fmt();
+                let now_empty = members.is_empty();
+                cleanups.extend(stale.into_iter().map(|(_, member)| {
+                    FeatureCleanup::RemovedMember {
+                        feature: feature.clone(),
+                        member,
+                    }
+                }));
+                if now_empty {
+                    *item = toml_edit::Item::None;
+                    removed_features.push(feature.clone());
+                    cleanups.push(FeatureCleanup::RemovedFeature(feature.clone()));
+                }
+                changed = true;
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        // remove table if empty
+        if self.data["features"]
+            .as_table_like()
+            .map(toml_edit::TableLike::is_empty)
+            .unwrap_or(false)
+        {
+            self.data["features"] = toml_edit::Item::None;
+        }
+
+        cleanups
+    }
+
     /// Add multiple dependencies to manifest
     pub fn add_deps(&mut self, table: &[String], deps: &[Dependency]) -> Result<()> {
         deps.iter()
@@ -414,28 +988,112 @@ impl LocalManifest {
 
     /// Instruct this manifest to upgrade a single dependency. If this manifest does not have that
     /// dependency, it does nothing.
-    pub fn upgrade(&mut self, dependency: &Dependency, dry_run: bool) -> Result<()> {
+    ///
+    /// Dependencies inherited with `workspace = true` are left alone, see
+    /// `upgrade_workspace_dependency`.
+    ///
+    /// If `requirement` is given, only the entries with that version requirement are upgraded, as
+    /// the version to upgrade to may have been chosen to match it.
+    ///
+    /// If `preserve_requirement` is set, the entries keep the operator and the precision of their
+    /// requirements, e.g. `~1.2` is upgraded to `~1.4` rather than `1.4.0`.
+    ///
+    /// Returns the changes made to the entries' version requirements, which are left for the
+    /// caller to report.
+    pub fn upgrade(
+        &mut self,
+        dependency: &Dependency,
+        requirement: Option<&semver::VersionReq>,
+        preserve_requirement: bool,
+        dry_run: bool,
+    ) -> Result<Vec<VersionUpgrade>> {
+        let mut upgrades = Vec::new();
         for (table_path, table) in self.get_sections() {
-            let table_like = table.as_table_like().expect("Unexpected non-table");
-            for (name, toml_item) in table_like.iter() {
-                let dep_name = toml_item
-                    .as_table_like()
-                    .and_then(|t| t.get("package").and_then(|p| p.as_str()))
-                    .unwrap_or(name);
-                if dep_name == dependency.name {
-                    self.manifest.update_table_named_entry(
-                        &table_path,
-                        &name,
-                        dependency,
-                        dry_run,
-                    )?;
-                }
-            }
+            upgrades.extend(self.upgrade_table(
+                &table_path,
+                &table,
+                dependency,
+                requirement,
+                preserve_requirement,
+                dry_run,
+            )?);
+        }
+
+        let mut file = self.get_file()?;
+        self.write_to_file(&mut file)
+            .chain_err(|| "Failed to write new manifest contents")?;
+        Ok(upgrades)
+    }
+
+    /// Instruct this manifest to upgrade a single dependency in its `[workspace.dependencies]`
+    /// table, which workspace members inherit from. If the table does not have that dependency,
+    /// it does nothing. `requirement` and `preserve_requirement` are handled as in `upgrade`.
+    pub fn upgrade_workspace_dependency(
+        &mut self,
+        dependency: &Dependency,
+        requirement: Option<&semver::VersionReq>,
+        preserve_requirement: bool,
+        dry_run: bool,
+    ) -> Result<Vec<VersionUpgrade>> {
+        let mut upgrades = Vec::new();
+        let table = self.data["workspace"]["dependencies"].clone();
+        if table.is_table_like() {
+            let table_path = vec!["workspace".to_owned(), "dependencies".to_owned()];
+            upgrades = self.upgrade_table(
+                &table_path,
+                &table,
+                dependency,
+                requirement,
+                preserve_requirement,
+                dry_run,
+            )?;
         }
 
         let mut file = self.get_file()?;
         self.write_to_file(&mut file)
-            .chain_err(|| "Failed to write new manifest contents")
+            .chain_err(|| "Failed to write new manifest contents")?;
+        Ok(upgrades)
+    }
+
+    /// Upgrade the entries of a single table referring to `dependency` with `requirement`, if
+    /// given.
+    fn upgrade_table(
+        &mut self,
+        table_path: &[String],
+        table: &toml_edit::Item,
+        dependency: &Dependency,
+        requirement: Option<&semver::VersionReq>,
+        preserve_requirement: bool,
+        dry_run: bool,
+    ) -> Result<Vec<VersionUpgrade>> {
+        let mut upgrades = Vec::new();
+        let table_like = table.as_table_like().expect("Unexpected non-table");
+        for (name, toml_item) in table_like.iter() {
+            let dep = Dependency::from_toml(name, toml_item);
+            // The version of an inherited dependency lives in the workspace root.
+            if dep.as_ref().map(Dependency::inherited).unwrap_or(false) {
+                continue;
+            }
+            let entry_requirement = dep
+                .as_ref()
+                .ok()
+                .and_then(Dependency::version)
+                .and_then(|version| semver::VersionReq::parse(version).ok());
+            if requirement.is_some() && entry_requirement.as_ref() != requirement {
+                continue;
+            }
+            let dep_name = dep.map(|dep| dep.name).unwrap_or_else(|_| name.to_owned());
+            if dep_name == dependency.name {
+                upgrades.extend(self.manifest.merge_table_entry(
+                    table_path,
+                    &name,
+                    dependency,
+                    preserve_requirement,
+                    dry_run,
+                )?);
+            }
+        }
+        Ok(upgrades)
     }
 }
 
@@ -445,6 +1103,56 @@ mod tests {
     use crate::dependency::Dependency;
     use toml_edit;
 
+    #[test]
+    fn upgrade_kinds() {
+        assert_eq!(lowest_version("=1.2.3"), Some((1, 2, 3)));
+        assert_eq!(lowest_version(">= 0.9, < 2.0"), Some((0, 9, 0)));
+        assert_eq!(lowest_version("~1.2.3-alpha.1"), Some((1, 2, 3)));
+        assert_eq!(lowest_version("1.*"), Some((1, 0, 0)));
+        assert_eq!(lowest_version("*"), None);
+
+        assert_eq!(UpgradeKind::between("1", "2.0.0"), Some(UpgradeKind::Major));
+        assert_eq!(UpgradeKind::between("1", "1.3.0"), Some(UpgradeKind::Minor));
+        assert_eq!(
+            UpgradeKind::between("0.2", "0.2.65"),
+            Some(UpgradeKind::Minor)
+        );
+        assert_eq!(
+            UpgradeKind::between("0.2", "0.3.0"),
+            Some(UpgradeKind::Major)
+        );
+        assert_eq!(
+            UpgradeKind::between("0.0.3", "0.0.4"),
+            Some(UpgradeKind::Major)
+        );
+        assert_eq!(
+            UpgradeKind::between("1.2.3", "1.2.4"),
+            Some(UpgradeKind::Patch)
+        );
+        assert_eq!(
+            UpgradeKind::between("1.0.0-alpha", "1.0.0"),
+            Some(UpgradeKind::Patch)
+        );
+        assert_eq!(UpgradeKind::between("1.2", "1.2.0"), None);
+        assert_eq!(UpgradeKind::between("1.2", "latest"), None);
+    }
+
+    #[test]
+    fn upgrade_requirements() {
+        assert_eq!(upgrade_requirement("~1.2", "1.4.0"), "~1.4");
+        assert_eq!(upgrade_requirement("=0.3.1", "0.5.0"), "=0.5.0");
+        assert_eq!(upgrade_requirement("1", "2.0.3"), "2");
+        assert_eq!(upgrade_requirement("^0.8", "1.1.0"), "^1.1");
+        assert_eq!(upgrade_requirement(">= 1.0", "2.0.3"), ">=2.0");
+        assert_eq!(upgrade_requirement("0.8", "1.2.0-alpha.1"), "1.2.0-alpha.1");
+        assert_eq!(upgrade_requirement(">=1.0, <3", "3.1.0"), ">=1.0, <4");
+        assert_eq!(upgrade_requirement(">=0.1, <0.3", "0.3.2"), ">=0.1, <0.4");
+        assert_eq!(upgrade_requirement(">=1.0, <=2.5", "3.1.0"), ">=1.0, <=3.1");
+        assert_eq!(upgrade_requirement(">=1.0, <3", "2.1.0"), ">=1.0, <3");
+        assert_eq!(upgrade_requirement("1.*", "2.0.3"), "2.0.3");
+        assert_eq!(upgrade_requirement("1.2", "latest"), "latest");
+    }
+
     #[test]
     fn add_remove_dependency() {
         let mut manifest = Manifest {
@@ -454,7 +1162,7 @@ mod tests {
         let dep = Dependency::new("cargo-edit").set_version("0.1.0");
         let _ = manifest.insert_into_table(&["dependencies".to_owned()], &dep);
         assert!(manifest
-            .remove_from_table("dependencies", &dep.name)
+            .remove_from_table(&["dependencies".to_owned()], &dep.name)
             .is_ok());
         assert_eq!(manifest.data.to_string(), clone.data.to_string());
     }
@@ -475,6 +1183,91 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn add_features_to_existing_dependency() {
+        let mut manifest: Manifest = r#"
+[dependencies]
+tokio = { version = "0.2", features = ["rt", "net"] }
+"#
+        .parse()
+        .unwrap();
+
+        let dep = Dependency::new("tokio")
+            .set_version("0.2")
+            .set_features(Some(vec!["macros".to_owned(), "rt".to_owned()]));
+        manifest
+            .insert_into_table(&["dependencies".to_owned()], &dep)
+            .unwrap();
+
+        assert_eq!(
+            get_features(&manifest.data["dependencies"]["tokio"]),
+            vec!["rt", "net", "macros"]
+        );
+    }
+
+    #[test]
+    fn inherit_existing_dependency() {
+        let mut manifest: Manifest = r#"
+[dependencies]
+serde = { version = "1.0", default-features = false, features = ["derive"] }
+"#
+        .parse()
+        .unwrap();
+
+        let dep = Dependency::new("serde")
+            .set_inherited()
+            .set_features(Some(vec!["std".to_owned()]));
+        manifest
+            .insert_into_table(&["dependencies".to_owned()], &dep)
+            .unwrap();
+
+        let entry = &manifest.data["dependencies"]["serde"];
+        assert_eq!(entry["workspace"].as_bool(), Some(true));
+        assert!(entry["version"].is_none());
+        assert!(entry["default-features"].is_none());
+        assert_eq!(get_features(entry), vec!["derive", "std"]);
+    }
+
+    #[test]
+    fn get_typed_dependencies() {
+        let manifest: Manifest = r#"
+[dependencies]
+docopt = "0.8"
+te = { package = "toml_edit", version = "0.1.5" }
+
+[dev-dependencies.tempdir]
+version = "0.3"
+
+[target.'cfg(unix)'.build-dependencies]
+cc = { path = "../cc" }
+"#
+        .parse()
+        .unwrap();
+
+        let deps = manifest
+            .get_dependencies()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let summary = deps
+            .iter()
+            .map(|d| (d.kind, d.target.as_deref(), d.key.as_str(), d.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (DependencyKind::Development, None, "tempdir", "tempdir"),
+                (DependencyKind::Build, Some("cfg(unix)"), "cc", "cc"),
+                (DependencyKind::Normal, None, "docopt", "docopt"),
+                (DependencyKind::Normal, None, "te", "toml_edit"),
+            ]
+        );
+        assert_eq!(deps[1].dependency.path(), Some("../cc"));
+        assert_eq!(
+            deps[1].table_path(),
+            vec!["target", "cfg(unix)", "build-dependencies"]
+        );
+    }
+
     #[test]
     fn update_wrong_dependency() {
         let mut manifest = Manifest {
@@ -494,6 +1287,159 @@ mod tests {
         assert_eq!(manifest.data.to_string(), original.data.to_string());
     }
 
+    #[test]
+    fn remove_target_dependency() {
+        let mut manifest: Manifest = r#"
+[dependencies]
+docopt = "0.8"
+
+[target.'cfg(unix)'.dependencies]
+openssl = "0.9"
+
+[target.'cfg(windows)'.dependencies]
+winapi = "0.3"
+"#
+        .parse()
+        .unwrap();
+        let table = |target: &str| {
+            vec![
+                "target".to_owned(),
+                target.to_owned(),
+                "dependencies".to_owned(),
+            ]
+        };
+
+        manifest
+            .remove_from_table(&table("cfg(unix)"), "openssl")
+            .unwrap();
+        assert!(manifest.data["target"]["cfg(unix)"].is_none());
+        assert!(!manifest.data["target"]["cfg(windows)"].is_none());
+
+        manifest
+            .remove_from_table(&table("cfg(windows)"), "winapi")
+            .unwrap();
+        assert!(manifest.data["target"].is_none());
+        assert!(!manifest.data["dependencies"]["docopt"].is_none());
+
+        assert!(manifest
+            .remove_from_table(&table("cfg(unix)"), "openssl")
+            .is_err());
+        assert!(manifest.data["target"].is_none());
+    }
+
+    #[test]
+    fn remove_dependency_from_all_tables() {
+        let mut manifest: Manifest = r#"
+[dependencies]
+semver = "0.1"
+docopt = "0.8"
+
+[build-dependencies]
+semver = "0.1"
+
+[target.'cfg(unix)'.dev-dependencies]
+semver = "0.1"
+"#
+        .parse()
+        .unwrap();
+
+        let removed = manifest.remove_from_all_tables("semver").unwrap();
+        assert_eq!(
+            removed,
+            vec![
+                vec![
+                    "target".to_owned(),
+                    "cfg(unix)".to_owned(),
+                    "dev-dependencies".to_owned()
+                ],
+                vec!["build-dependencies".to_owned()],
+                vec!["dependencies".to_owned()],
+            ]
+        );
+        assert!(manifest.data["build-dependencies"].is_none());
+        assert!(manifest.data["target"].is_none());
+        assert!(!manifest.data["dependencies"]["docopt"].is_none());
+
+        assert!(manifest.remove_from_all_tables("semver").is_err());
+    }
+
+    #[test]
+    fn remove_feature_references_to_dependency() {
+        let mut manifest: Manifest = r#"
+[dependencies]
+serde = { version = "1.0", optional = true }
+serde_json = { version = "1.0", optional = true }
+
+[features]
+default = []
+json = ["serde_json", "serde/derive"]
+serde-support = ["dep:serde"]
+all = ["json", "serde-support", "serde?/std"]
+"#
+        .parse()
+        .unwrap();
+
+        manifest
+            .remove_from_table(&["dependencies".to_owned()], "serde")
+            .unwrap();
+        let cleanups = manifest.remove_feature_references("serde");
+
+        assert_eq!(
+            cleanups,
+            vec![
+                FeatureCleanup::RemovedMember {
+                    feature: "json".to_owned(),
+                    member: "serde/derive".to_owned(),
+                },
+                FeatureCleanup::RemovedMember {
+                    feature: "serde-support".to_owned(),
+                    member: "dep:serde".to_owned(),
+                },
+                FeatureCleanup::RemovedFeature("serde-support".to_owned()),
+                FeatureCleanup::RemovedMember {
+                    feature: "all".to_owned(),
+                    member: "serde-support".to_owned(),
+                },
+                FeatureCleanup::RemovedMember {
+                    feature: "all".to_owned(),
+                    member: "serde?/std".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(
+            manifest.data["features"].as_table().unwrap().to_string(),
+            r#"default = []
+json = ["serde_json"]
+all = ["json"]
+"#
+        );
+    }
+
+    #[test]
+    fn keep_feature_references_to_remaining_dependency() {
+        let mut manifest: Manifest = r#"
+[dependencies]
+serde = { version = "1.0", optional = true }
+
+[dev-dependencies]
+serde = "1.0"
+
+[features]
+serde-support = ["serde"]
+"#
+        .parse()
+        .unwrap();
+
+        manifest
+            .remove_from_table(&["dev-dependencies".to_owned()], "serde")
+            .unwrap();
+        assert!(manifest.remove_feature_references("serde").is_empty());
+        assert_eq!(
+            manifest.data["features"].as_table().unwrap().to_string(),
+            "serde-support = [\"serde\"]\n"
+        );
+    }
+
     #[test]
     fn remove_dependency_no_section() {
         let mut manifest = Manifest {
@@ -501,7 +1447,7 @@ mod tests {
         };
         let dep = Dependency::new("cargo-edit").set_version("0.1.0");
         assert!(manifest
-            .remove_from_table("dependencies", &dep.name)
+            .remove_from_table(&["dependencies".to_owned()], &dep.name)
             .is_err());
     }
 
@@ -514,7 +1460,7 @@ mod tests {
         let other_dep = Dependency::new("other-dep").set_version("0.1.0");
         let _ = manifest.insert_into_table(&["dependencies".to_owned()], &other_dep);
         assert!(manifest
-            .remove_from_table("dependencies", &dep.name)
+            .remove_from_table(&["dependencies".to_owned()], &dep.name)
             .is_err());
     }
 }
@@ -1,16 +1,17 @@
+pub use self::code_from_cargo::GitReference;
 use self::code_from_cargo::Kind;
+use crate::config::{cargo_home, CargoConfig};
 use crate::errors::*;
-use serde::Deserialize;
-use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use url::Url;
 
-const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
-const CRATES_IO_REGISTRY: &str = "crates-io";
-
-pub fn registry_path(manifest_path: &Path, registry: Option<&str>) -> Result<PathBuf> {
-    registry_path_from_url(&registry_url(manifest_path, registry)?)
-}
+pub(crate) const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+pub(crate) const CRATES_IO_SPARSE_INDEX: &str = "sparse+https://index.crates.io/";
+pub(crate) const CRATES_IO_REGISTRY: &str = "crates-io";
+/// The prefix of the URLs standing for `local-registry` sources
+pub(crate) const LOCAL_REGISTRY_PREFIX: &str = "local-registry+";
+/// The prefix of the URLs standing for `directory` sources
+pub(crate) const DIRECTORY_PREFIX: &str = "directory+";
 
 pub fn registry_path_from_url(registry: &Url) -> Result<PathBuf> {
     Ok(cargo_home()?
@@ -19,104 +20,47 @@ pub fn registry_path_from_url(registry: &Url) -> Result<PathBuf> {
         .join(short_name(registry)))
 }
 
-#[derive(Debug, Deserialize)]
-struct Source {
-    #[serde(rename = "replace-with")]
-    replace_with: Option<String>,
-    registry: Option<String>,
+/// Get the directory cargo downloads the `.crate` files of a registry to.
+pub(crate) fn registry_cache_path_from_url(registry: &Url) -> Result<PathBuf> {
+    Ok(cargo_home()?
+        .join("registry")
+        .join("cache")
+        .join(short_name(registry)))
 }
 
-#[derive(Debug, Deserialize)]
-struct Registry {
-    index: Option<String>,
+/// A source on the local file system, which a registry can be replaced with
+#[derive(Debug, PartialEq)]
+pub(crate) enum LocalSource {
+    /// A local registry, i.e. an `index` directory next to the `.crate` files
+    Registry(PathBuf),
+    /// A directory of unpacked crates, as created by `cargo vendor`
+    Directory(PathBuf),
 }
 
-#[derive(Debug, Deserialize)]
-struct CargoConfig {
-    #[serde(default)]
-    registries: HashMap<String, Registry>,
-    #[serde(default)]
-    source: HashMap<String, Source>,
+/// Get the local source a URL returned by `registry_url` stands for, if any.
+pub(crate) fn local_source(registry: &Url) -> Option<LocalSource> {
+    let path = |prefix: &str| {
+        let url = Url::parse(registry.as_str().strip_prefix(prefix)?).ok()?;
+        url.to_file_path().ok()
+    };
+    path(LOCAL_REGISTRY_PREFIX)
+        .map(LocalSource::Registry)
+        .or_else(|| path(DIRECTORY_PREFIX).map(LocalSource::Directory))
 }
 
-fn cargo_home() -> Result<PathBuf> {
-    let default_cargo_home = dirs::home_dir()
-        .map(|x| x.join(".cargo"))
-        .chain_err(|| ErrorKind::ReadHomeDirFailure)?;
-    let cargo_home = std::env::var("CARGO_HOME")
-        .map(PathBuf::from)
-        .unwrap_or(default_cargo_home);
-    Ok(cargo_home)
+/// Get the URL standing for the local source at `path`, marked with `prefix`.
+pub(crate) fn local_source_url(prefix: &str, path: &Path) -> Option<Url> {
+    let path = std::env::current_dir().ok()?.join(path);
+    let url = Url::from_directory_path(path).ok()?;
+    Url::parse(&format!("{}{}", prefix, url)).ok()
 }
 
 /// Find the URL of a registry
+///
+/// See `CargoConfig::registry_url`, which this calls with the configuration applying to the
+/// manifest at `manifest_path`.
 pub fn registry_url(manifest_path: &Path, registry: Option<&str>) -> Result<Url> {
-    // TODO support local registry sources, directory sources, git sources: https://doc.rust-lang.org/cargo/reference/source-replacement.html?highlight=replace-with#source-replacement
-    fn read_config(registries: &mut HashMap<String, Source>, path: impl AsRef<Path>) -> Result<()> {
-        // TODO unit test for source replacement
-        let content = std::fs::read(path)?;
-        let config =
-            toml::from_slice::<CargoConfig>(&content).map_err(|_| ErrorKind::InvalidCargoConfig)?;
-        for (key, value) in config.registries {
-            registries.entry(key).or_insert(Source {
-                registry: value.index,
-                replace_with: None,
-            });
-        }
-        for (key, value) in config.source {
-            registries.entry(key).or_insert(value);
-        }
-        Ok(())
-    }
-    // registry might be replaced with another source
-    // it's looks like a singly linked list
-    // put relations in this map.
-    let mut registries: HashMap<String, Source> = HashMap::new();
-    // ref: https://doc.rust-lang.org/cargo/reference/config.html#hierarchical-structure
-    for work_dir in manifest_path
-        .parent()
-        .expect("there must be a parent directory")
-        .ancestors()
-    {
-        let config_path = work_dir.join(".cargo").join("config");
-        if config_path.is_file() {
-            read_config(&mut registries, config_path)?;
-        }
-    }
-
-    let default_config_path = cargo_home()?.join("config");
-    if default_config_path.is_file() {
-        read_config(&mut registries, default_config_path)?;
-    }
-
-    // find head of the relevant linked list
-    let mut source = match registry {
-        Some(CRATES_IO_INDEX) | None => {
-            registries
-                .remove(CRATES_IO_REGISTRY)
-                .unwrap_or_else(|| Source {
-                    replace_with: None,
-                    registry: Some(CRATES_IO_INDEX.to_string()),
-                })
-        }
-        Some(r) => registries
-            .remove(r)
-            .chain_err(|| ErrorKind::NoSuchRegistryFound(r.to_string()))?,
-    };
-
-    // search this linked list and find the tail
-    while let Some(replace_with) = &source.replace_with {
-        source = registries
-            .remove(replace_with)
-            .chain_err(|| ErrorKind::NoSuchSourceFound(replace_with.to_string()))?;
-    }
-
-    let registry_url = source
-        .registry
-        .and_then(|x| Url::parse(&x).ok())
-        .chain_err(|| ErrorKind::InvalidCargoConfig)?;
-
-    Ok(registry_url)
+    CargoConfig::load(manifest_path, &[])?.registry_url(registry)
 }
 
 fn short_name(registry: &Url) -> String {
@@ -146,6 +90,34 @@ fn test_short_name() {
     );
 }
 
+#[test]
+fn test_local_source_replacement() {
+    let dir = tempdir::TempDir::new("cargo-edit-test").unwrap();
+    let manifest_path = dir.path().join("Cargo.toml");
+    std::fs::create_dir(dir.path().join(".cargo")).unwrap();
+    for kind in &["local-registry", "directory"] {
+        std::fs::write(
+            dir.path().join(".cargo").join("config"),
+            format!(
+                "[source.crates-io]\nreplace-with = \"vendored\"\n\
+                 [source.vendored]\n{} = \"vendor\"\n",
+                kind
+            ),
+        )
+        .unwrap();
+        let url = registry_url(&manifest_path, None).unwrap();
+        assert!(url.as_str().starts_with(&format!("{}+file://", kind)));
+
+        let vendor = dir.path().join("vendor");
+        let expected = if *kind == "directory" {
+            LocalSource::Directory(vendor)
+        } else {
+            LocalSource::Registry(vendor)
+        };
+        assert_eq!(local_source(&url), Some(expected));
+    }
+}
+
 mod code_from_cargo {
     #![allow(dead_code)]
 
@@ -158,10 +130,14 @@ mod code_from_cargo {
         Directory,
     }
 
+    /// Information to find a specific commit in a git repository.
     #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub enum GitReference {
+        /// From a tag.
         Tag(String),
+        /// From the HEAD of a branch.
         Branch(String),
+        /// From a specific revision.
         Rev(String),
     }
 }
@@ -0,0 +1,208 @@
+//! Access to registry indices using the sparse HTTP protocol
+//!
+//! Instead of cloning the whole index, a sparse registry serves the summary file of every crate
+//! at the same relative path it has in a git index. Fetched summaries are cached on disk along
+//! with their `ETag`/`Last-Modified` headers, so unchanged files are not downloaded again.
+//! Offline, only the cached summaries are read. The token of the registry, if any, is sent with
+//! every request.
+
+use crate::config::Token;
+use crate::errors::*;
+use crate::fetch::http_client;
+use crate::index::{is_cached_crate, parse_summary, summary_raw_path, CrateVersion, RegistryIndex};
+use reqwest::header::{AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use std::fs;
+use std::path::PathBuf;
+use url::Url;
+
+/// The prefix marking the URL of a sparse registry
+const SPARSE_PREFIX: &str = "sparse+";
+
+/// Check whether a registry URL uses the sparse protocol, e.g. `sparse+https://index.crates.io/`.
+pub fn is_sparse(registry: &Url) -> bool {
+    registry.as_str().starts_with(SPARSE_PREFIX)
+}
+
+/// A summary file as cached on disk
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedSummary {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    content: String,
+}
+
+/// A sparse registry index, queried over HTTP
+#[derive(Debug)]
+pub struct SparseIndex {
+    /// The URL summary paths are relative to
+    base: Url,
+    /// The directory fetched summaries are cached in
+    cache_path: PathBuf,
+    /// The directory the `.crate` files of the registry are downloaded to, set when offline
+    crate_cache: Option<PathBuf>,
+    token: Option<Token>,
+}
+
+impl SparseIndex {
+    /// Create an index for a `sparse+` registry URL, caching summaries in `cache_path`.
+    pub fn new(registry: &Url, cache_path: PathBuf) -> Result<Self> {
+        let base = registry.as_str().trim_start_matches(SPARSE_PREFIX);
+        let base = if base.ends_with('/') {
+            base.to_owned()
+        } else {
+            format!("{}/", base)
+        };
+        let base = Url::parse(&base).chain_err(|| ErrorKind::InvalidCargoConfig)?;
+        Ok(SparseIndex {
+            base,
+            cache_path,
+            crate_cache: None,
+            token: None,
+        })
+    }
+
+    /// Authenticate with `token`, which is sent as the `Authorization` header like cargo does.
+    pub fn token(self, token: String) -> Self {
+        SparseIndex {
+            token: Some(Token::from(token)),
+            ..self
+        }
+    }
+
+    /// Work offline, reading cached summaries only and preferring the versions downloaded to
+    /// `crate_cache`.
+    pub fn offline(self, crate_cache: PathBuf) -> Self {
+        SparseIndex {
+            crate_cache: Some(crate_cache),
+            ..self
+        }
+    }
+
+    /// Get the URL of the summary at `raw_path`.
+    fn summary_url(&self, raw_path: &str) -> Result<Url> {
+        self.base
+            .join(&raw_path.to_lowercase())
+            .chain_err(|| ErrorKind::InvalidCargoConfig)
+    }
+
+    /// Get the summary at `raw_path`, or `None` if the registry has no such file.
+    ///
+    /// A cached summary is revalidated with the registry before it is used, unless offline.
+    fn summary(&self, raw_path: &str) -> Result<Option<String>> {
+        let url = self.summary_url(raw_path)?;
+        let cache_file = self.cache_path.join(raw_path.to_lowercase());
+        let cached = fs::read(&cache_file)
+            .ok()
+            .and_then(|content| serde_json::from_slice::<CachedSummary>(&content).ok());
+        if self.is_offline() {
+            return Ok(cached.map(|c| c.content));
+        }
+
+        let mut request = http_client()?.get(url.as_str());
+        if let Some(ref token) = self.token {
+            request = request.header(AUTHORIZATION, token.secret());
+        }
+        if let Some(ref cached) = cached {
+            if let Some(ref etag) = cached.etag {
+                request = request.header(IF_NONE_MATCH, etag.as_str());
+            } else if let Some(ref last_modified) = cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+            }
+        }
+        let mut response = request
+            .send()
+            .chain_err(|| ErrorKind::IndexRequestFailed(url.to_string()))?;
+
+        match response.status() {
+            StatusCode::NOT_MODIFIED if cached.is_some() => Ok(cached.map(|c| c.content)),
+            // Cargo treats "Unavailable For Legal Reasons" like a missing crate as well.
+            StatusCode::NOT_FOUND
+            | StatusCode::GONE
+            | StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS => Ok(None),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(ErrorKind::IndexUnauthorized(self.base.to_string()).into())
+            }
+            status if status.is_success() => {
+                let header = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .map(String::from)
+                };
+                let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+                let content = response
+                    .text()
+                    .chain_err(|| ErrorKind::IndexRequestFailed(url.to_string()))?;
+
+                let summary = CachedSummary {
+                    etag,
+                    last_modified,
+                    content,
+                };
+                if let Some(dir) = cache_file.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(
+                    &cache_file,
+                    serde_json::to_vec(&summary).chain_err(|| "Failed to cache index summary")?,
+                )?;
+                Ok(Some(summary.content))
+            }
+            status => Err(Error::from(format!(
+                "The registry responded with {}",
+                status
+            )))
+            .chain_err(|| ErrorKind::IndexRequestFailed(url.to_string())),
+        }
+    }
+}
+
+impl RegistryIndex for SparseIndex {
+    fn crate_versions(&self, crate_name: &str) -> Result<Option<Vec<CrateVersion>>> {
+        match self.summary(&summary_raw_path(crate_name))? {
+            Some(content) => parse_summary(&content).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn is_offline(&self) -> bool {
+        self.crate_cache.is_some()
+    }
+
+    fn is_downloaded(&self, version: &CrateVersion) -> bool {
+        self.crate_cache
+            .as_ref()
+            .map_or(false, |crate_cache| is_cached_crate(crate_cache, version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_sparse_urls() {
+        assert!(is_sparse(
+            &Url::parse("sparse+https://index.crates.io/").unwrap()
+        ));
+        assert!(!is_sparse(
+            &Url::parse("https://github.com/rust-lang/crates.io-index").unwrap()
+        ));
+    }
+
+    #[test]
+    fn summary_urls() {
+        for registry in &[
+            "sparse+https://example.com/index",
+            "sparse+https://example.com/index/",
+        ] {
+            let index = SparseIndex::new(&Url::parse(registry).unwrap(), PathBuf::new()).unwrap();
+            assert_eq!(
+                index.summary_url("se/rd/Serde").unwrap().as_str(),
+                "https://example.com/index/se/rd/serde"
+            );
+        }
+    }
+}
@@ -1,11 +1,13 @@
 #[macro_use]
 extern crate pretty_assertions;
 
-use std::process;
+use std::path::Path;
+use std::{fs, process};
 mod utils;
 use crate::utils::{
-    clone_out_test, execute_bad_command, execute_command, get_command_path, get_toml,
-    setup_alt_registry_config,
+    clone_out_test, create_git_repo, execute_bad_command, execute_command,
+    execute_command_with_registries, get_command_path, get_toml, setup_alt_registry_config,
+    setup_test_index, IndexServer,
 };
 
 /// Some of the tests need to have a crate name that does not exist on crates.io. Hence this rather
@@ -33,7 +35,7 @@ fn adds_dependency() {
     // dependency present afterwards
     let toml = get_toml(&manifest);
     let val = &toml["dependencies"]["my-package"];
-    assert_eq!(val.as_str().unwrap(), "my-package--CURRENT_VERSION_TEST");
+    assert_eq!(val.as_str().unwrap(), "0.4.1");
 }
 
 #[test]
@@ -49,7 +51,7 @@ fn adds_prerelease_dependency() {
     // dependency present afterwards
     let toml = get_toml(&manifest);
     let val = &toml["dependencies"]["my-package"];
-    assert_eq!(val.as_str().unwrap(), "my-package--PRERELEASE_VERSION_TEST");
+    assert_eq!(val.as_str().unwrap(), "0.5.0-alpha.1");
 }
 
 fn upgrade_test_helper(upgrade_method: &str, expected_prefix: &str) {
@@ -67,7 +69,7 @@ fn upgrade_test_helper(upgrade_method: &str, expected_prefix: &str) {
     let toml = get_toml(&manifest);
     let val = &toml["dependencies"]["my-package"];
 
-    let expected_result = format!("{0}my-package--CURRENT_VERSION_TEST", expected_prefix);
+    let expected_result = format!("{0}0.4.1", expected_prefix);
     assert_eq!(val.as_str().unwrap(), expected_result);
 }
 
@@ -113,9 +115,9 @@ fn adds_multiple_dependencies() {
     // dependencies present afterwards
     let toml = get_toml(&manifest);
     let val = &toml["dependencies"]["my-package1"];
-    assert_eq!(val.as_str().unwrap(), "my-package1--CURRENT_VERSION_TEST");
+    assert_eq!(val.as_str().unwrap(), "0.3.1");
     let val = &toml["dependencies"]["my-package2"];
-    assert_eq!(val.as_str().unwrap(), "my-package2--CURRENT_VERSION_TEST");
+    assert_eq!(val.as_str().unwrap(), "0.3.2");
 }
 
 #[test]
@@ -131,10 +133,7 @@ fn adds_renamed_dependency() {
     // dependency present afterwards
     let toml = get_toml(&manifest);
     let renamed = &toml["dependencies"]["renamed"];
-    assert_eq!(
-        renamed["version"].as_str().unwrap(),
-        "my-package1--CURRENT_VERSION_TEST"
-    );
+    assert_eq!(renamed["version"].as_str().unwrap(), "0.3.1");
     assert_eq!(renamed["package"].as_str().unwrap(), "my-package1");
 }
 
@@ -195,15 +194,9 @@ fn adds_dev_build_dependency() {
     // dependency present afterwards
     let toml = get_toml(&manifest);
     let val = &toml["dev-dependencies"]["my-dev-package"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-dev-package--CURRENT_VERSION_TEST"
-    );
+    assert_eq!(val.as_str().unwrap(), "0.2.0");
     let val = &toml["build-dependencies"]["my-build-package"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-build-package--CURRENT_VERSION_TEST"
-    );
+    assert_eq!(val.as_str().unwrap(), "0.1.0");
 
     // cannot run with both --dev and --build at the same time
     let call = process::Command::new(get_command_path("add").as_str())
@@ -239,25 +232,13 @@ fn adds_multiple_dev_build_dependencies() {
     // dependencies present afterwards
     let toml = get_toml(&manifest);
     let val = &toml["dev-dependencies"]["my-dev-package1"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-dev-package1--CURRENT_VERSION_TEST"
-    );
+    assert_eq!(val.as_str().unwrap(), "0.2.1");
     let val = &toml["dev-dependencies"]["my-dev-package2"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-dev-package2--CURRENT_VERSION_TEST"
-    );
+    assert_eq!(val.as_str().unwrap(), "0.2.2");
     let val = &toml["build-dependencies"]["my-build-package1"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-build-package1--CURRENT_VERSION_TEST"
-    );
+    assert_eq!(val.as_str().unwrap(), "0.1.1");
     let val = &toml["build-dependencies"]["my-build-package2"];
-    assert_eq!(
-        val.as_str().unwrap(),
-        "my-build-package2--CURRENT_VERSION_TEST"
-    );
+    assert_eq!(val.as_str().unwrap(), "0.1.2");
 }
 
 #[test]
@@ -341,10 +322,7 @@ fn adds_multiple_dependencies_with_some_versions() {
     // dependencies present afterwards
     let toml = get_toml(&manifest);
     let val = &toml["dependencies"]["my-package1"];
-    assert_eq!(
-        val.as_str().expect("not string"),
-        "my-package1--CURRENT_VERSION_TEST"
-    );
+    assert_eq!(val.as_str().expect("not string"), "0.3.1");
     let val = &toml["dependencies"]["my-package2"];
     assert_eq!(val.as_str().expect("not string"), "0.2.3");
 }
@@ -388,6 +366,82 @@ fn adds_git_source_using_flag() {
     assert_eq!(val["git"].as_str(), Some("http://site/gp.git"));
 }
 
+#[test]
+fn adds_git_source_with_reference() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependency not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+
+    execute_command(
+        &[
+            "add",
+            "git-package",
+            "--git",
+            "http://localhost/git-package.git",
+            "--tag",
+            "v1.2",
+        ],
+        &manifest,
+    );
+    execute_command(
+        &[
+            "add",
+            "git-branch-package",
+            "--git",
+            "http://localhost/git-branch-package.git",
+            "--branch",
+            "dev",
+        ],
+        &manifest,
+    );
+    execute_command(
+        &[
+            "add",
+            "git-rev-package",
+            "--git",
+            "http://localhost/git-rev-package.git",
+            "--rev",
+            "0123abc",
+        ],
+        &manifest,
+    );
+
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["git-package"];
+    assert_eq!(
+        val["git"].as_str(),
+        Some("http://localhost/git-package.git")
+    );
+    assert_eq!(val["tag"].as_str(), Some("v1.2"));
+    let val = &toml["dependencies"]["git-branch-package"];
+    assert_eq!(val["branch"].as_str(), Some("dev"));
+    let val = &toml["dependencies"]["git-rev-package"];
+    assert_eq!(val["rev"].as_str(), Some("0123abc"));
+}
+
+#[test]
+fn git_reference_requires_git() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_bad_command(&["add", "git-package", "--tag", "v1.2"], &manifest);
+    execute_bad_command(
+        &[
+            "add",
+            "git-package",
+            "--git",
+            "http://localhost/git-package.git",
+            "--tag",
+            "v1.2",
+            "--branch",
+            "dev",
+        ],
+        &manifest,
+    );
+    assert!(get_toml(&manifest)["dependencies"].is_none());
+}
+
 #[test]
 fn adds_local_source_using_flag() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -789,6 +843,37 @@ fn adds_multiple_no_default_features_dependencies() {
         .expect("default-features not a bool"));
 }
 
+#[test]
+fn adds_dependency_with_features() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    // dependency not present beforehand
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"].is_none());
+
+    execute_command(&["add", "tokio@0.2", "--features", "rt,macros"], &manifest);
+
+    // dependency present afterwards
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["tokio"];
+    assert_eq!(val["version"].as_str(), Some("0.2"));
+    let features = val["features"].as_array().expect("features not an array");
+    assert_eq!(
+        features.iter().map(|f| f.as_str()).collect::<Vec<_>>(),
+        vec![Some("rt"), Some("macros")]
+    );
+}
+
+#[test]
+fn fails_to_add_multiple_dependencies_with_features() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+
+    execute_bad_command(
+        &["add", "my-package1", "my-package2", "--features", "foo"],
+        &manifest,
+    );
+}
+
 #[test]
 fn adds_alternative_registry_dependency() {
     let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -852,6 +937,577 @@ fn adds_multiple_alternative_registry_dependencies() {
     );
 }
 
+#[test]
+fn adds_dependency_from_sparse_registry() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let server = IndexServer::start(&[(
+        "my/-p/my-package",
+        "{\"name\":\"my-package\",\"vers\":\"0.1.0\",\"yanked\":false}\n\
+         {\"name\":\"my-package\",\"vers\":\"0.2.0\",\"yanked\":false}\n\
+         {\"name\":\"my-package\",\"vers\":\"0.3.0\",\"yanked\":true}\n",
+    )]);
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!("[registries.sparse]\nindex = \"sparse+{}\"\n", server.url),
+    )
+    .unwrap();
+
+    let cargo_home = tmpdir.path().join("cargo-home");
+    let add = || {
+        execute_command_with_registries(
+            &["add", "my-package", "--registry", "sparse"],
+            &manifest,
+            &cargo_home,
+            &[],
+        )
+    };
+
+    add();
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package"];
+    assert_eq!(val["version"].as_str(), Some("0.2.0"));
+    assert_eq!(val["registry"].as_str(), Some("sparse"));
+
+    // The cached summary is revalidated instead of fetched again.
+    add();
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].starts_with("get /my/-p/my-package "));
+    assert!(!requests[0].contains("if-none-match"));
+    assert!(requests[1].contains("if-none-match: \"1\""));
+}
+
+#[test]
+fn adds_dependency_from_authenticated_sparse_registry() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let server = IndexServer::start_authenticated(
+        &[(
+            "my/-p/my-package",
+            "{\"name\":\"my-package\",\"vers\":\"0.2.0\",\"yanked\":false}\n",
+        )],
+        "Bearer s3cr3t",
+    );
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!("[registries.company]\nindex = \"sparse+{}\"\n", server.url),
+    )
+    .unwrap();
+    let cargo_home = tmpdir.path().join("cargo-home");
+    let add = |envs: &[(&str, &str)]| {
+        process::Command::new(get_command_path("add"))
+            .args(["add", "my-package", "--registry", "company"])
+            .arg(format!("--manifest-path={}", manifest))
+            .env("CARGO_HOME", &cargo_home)
+            .envs(envs.iter().cloned())
+            .output()
+            .unwrap()
+    };
+
+    let call = add(&[]);
+    assert!(!call.status.success());
+    assert!(String::from_utf8_lossy(&call.stderr).contains("refused access"));
+
+    let call = add(&[("CARGO_REGISTRIES_COMPANY_TOKEN", "Bearer wrong")]);
+    assert!(!call.status.success());
+    assert!(!String::from_utf8_lossy(&call.stderr).contains("wrong"));
+    assert!(!String::from_utf8_lossy(&call.stdout).contains("wrong"));
+
+    let call = add(&[("CARGO_REGISTRIES_COMPANY_TOKEN", "Bearer s3cr3t")]);
+    assert!(call.status.success());
+    assert!(!String::from_utf8_lossy(&call.stdout).contains("s3cr3t"));
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"]["version"].as_str(),
+        Some("0.2.0")
+    );
+
+    // Tokens are read from the credentials of cargo as well.
+    fs::write(
+        cargo_home.join("credentials.toml"),
+        "[registries.company]\ntoken = \"Bearer s3cr3t\"\n",
+    )
+    .unwrap();
+    assert!(add(&[]).status.success());
+}
+
+#[test]
+fn adds_dependency_offline_from_downloaded_versions() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let server = IndexServer::start(&[(
+        "my/-p/my-package",
+        "{\"name\":\"my-package\",\"vers\":\"0.1.0\",\"yanked\":false}\n\
+         {\"name\":\"my-package\",\"vers\":\"0.2.0\",\"yanked\":false}\n",
+    )]);
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!("[registries.sparse]\nindex = \"sparse+{}\"\n", server.url),
+    )
+    .unwrap();
+    let cargo_home = tmpdir.path().join("cargo-home");
+    execute_command_with_registries(
+        &["add", "my-package", "--registry", "sparse"],
+        &manifest,
+        &cargo_home,
+        &[],
+    );
+
+    // Only 0.1.0 has been downloaded, so it is preferred offline.
+    let index = fs::read_dir(cargo_home.join("registry").join("index"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    let crate_cache = cargo_home
+        .join("registry")
+        .join("cache")
+        .join(index.file_name());
+    fs::create_dir_all(&crate_cache).unwrap();
+    fs::write(crate_cache.join("my-package-0.1.0.crate"), "").unwrap();
+    execute_command_with_registries(
+        &["add", "my-package", "--registry", "sparse"],
+        &manifest,
+        &cargo_home,
+        &[("CARGO_NET_OFFLINE", "true")],
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"]["version"].as_str(),
+        Some("0.1.0")
+    );
+    assert_eq!(server.requests().len(), 1);
+
+    // Crates whose summary was never fetched are not found.
+    let call = process::Command::new(get_command_path("add"))
+        .args(["add", "my-package1", "--registry", "sparse", "--offline"])
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_HOME", &cargo_home)
+        .output()
+        .unwrap();
+    assert!(!call.status.success());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn fails_to_access_network_offline() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let cargo_home = tmpdir.path().join("cargo-home");
+    let add = |crate_name: &str| {
+        process::Command::new(get_command_path("add"))
+            .args(["add", crate_name, "--offline"])
+            .arg(format!("--manifest-path={}", manifest))
+            .env("CARGO_HOME", &cargo_home)
+            .output()
+            .unwrap()
+    };
+
+    let call = add("https://github.com/killercup/cargo-edit");
+    assert!(!call.status.success());
+    assert!(String::from_utf8_lossy(&call.stderr)
+        .contains("Cannot access `https://github.com/killercup/cargo-edit` in offline mode."));
+
+    let call = add("my-package");
+    assert!(!call.status.success());
+    assert!(String::from_utf8_lossy(&call.stderr).contains(
+        "The registry index `https://github.com/rust-lang/crates.io-index` has not been \
+         downloaded"
+    ));
+}
+
+/// Replace crates.io with a source of kind `kind` in the `vendor` directory of `dir`
+fn setup_local_source(dir: &Path, kind: &str) {
+    fs::create_dir(dir.join(".cargo")).unwrap();
+    fs::write(
+        dir.join(".cargo").join("config.toml"),
+        format!(
+            "[source.crates-io]\nreplace-with = \"vendored\"\n\n\
+             [source.vendored]\n{} = \"vendor\"\n",
+            kind
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn adds_dependency_from_directory_source() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_local_source(tmpdir.path(), "directory");
+    for (dir, version) in &[("my-package", "0.2.0"), ("my-package-0.1.3", "0.1.3")] {
+        let dir = tmpdir.path().join("vendor").join(dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"my-package\"\nversion = \"{}\"\n",
+                version
+            ),
+        )
+        .unwrap();
+        fs::write(dir.join(".cargo-checksum.json"), "{\"files\":{}}").unwrap();
+    }
+    // Without a checksum file, this is not a vendored crate.
+    let dir = tmpdir.path().join("vendor").join("my-package-0.3.0");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"my-package\"\nversion = \"0.3.0\"\n",
+    )
+    .unwrap();
+
+    execute_command_with_registries(
+        &["add", "my-package"],
+        &manifest,
+        &tmpdir.path().join("cargo-home"),
+        &[],
+    );
+
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["my-package"].as_str(), Some("0.2.0"));
+}
+
+#[test]
+fn adds_dependency_from_local_registry() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_local_source(tmpdir.path(), "local-registry");
+    let summary = tmpdir.path().join("vendor/index/my/-p/my-package");
+    fs::create_dir_all(summary.parent().unwrap()).unwrap();
+    fs::write(
+        summary,
+        "{\"name\":\"my-package\",\"vers\":\"0.1.0\",\"deps\":[],\"yanked\":false}\n\
+         {\"name\":\"my-package\",\"vers\":\"0.1.1\",\"deps\":[],\"yanked\":false}\n",
+    )
+    .unwrap();
+
+    execute_command_with_registries(
+        &["add", "my-package"],
+        &manifest,
+        &tmpdir.path().join("cargo-home"),
+        &[],
+    );
+
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["my-package"].as_str(), Some("0.1.1"));
+}
+
+/// Start a sparse index serving `my-package` in versions 0.1.0 and 0.2.0
+fn start_my_package_index() -> IndexServer {
+    IndexServer::start(&[(
+        "my/-p/my-package",
+        "{\"name\":\"my-package\",\"vers\":\"0.1.0\",\"yanked\":false}\n\
+         {\"name\":\"my-package\",\"vers\":\"0.2.0\",\"yanked\":false}\n",
+    )])
+}
+
+#[test]
+fn adds_dependency_from_default_registry() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let server = start_my_package_index();
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config.toml"),
+        "[registry]\ndefault = \"company\"\n",
+    )
+    .unwrap();
+
+    execute_command_with_registries(
+        &["add", "my-package"],
+        &manifest,
+        &tmpdir.path().join("cargo-home"),
+        &[(
+            "CARGO_REGISTRIES_COMPANY_INDEX",
+            &format!("sparse+{}", server.url),
+        )],
+    );
+
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package"];
+    assert_eq!(val["version"].as_str(), Some("0.2.0"));
+    assert_eq!(val["registry"].as_str(), Some("company"));
+}
+
+#[test]
+fn adds_dependency_with_version_from_default_registry() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config.toml"),
+        "[registry]\ndefault = \"company\"\n",
+    )
+    .unwrap();
+
+    execute_command_with_registries(
+        &["add", "my-package@0.1.0"],
+        &manifest,
+        &tmpdir.path().join("cargo-home"),
+        &[(
+            "CARGO_REGISTRIES_COMPANY_INDEX",
+            "sparse+http://127.0.0.1:1/",
+        )],
+    );
+
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package"];
+    assert_eq!(val["version"].as_str(), Some("0.1.0"));
+    assert_eq!(val["registry"].as_str(), Some("company"));
+}
+
+#[test]
+fn adds_dependency_from_registry_set_with_config_argument() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let server = start_my_package_index();
+
+    execute_command_with_registries(
+        &[
+            "add",
+            "my-package",
+            "--config",
+            &format!("registries.company.index = \"sparse+{}\"", server.url),
+        ],
+        &manifest,
+        &tmpdir.path().join("cargo-home"),
+        &[("CARGO_REGISTRY_DEFAULT", "company")],
+    );
+
+    let toml = get_toml(&manifest);
+    let val = &toml["dependencies"]["my-package"];
+    assert_eq!(val["version"].as_str(), Some("0.2.0"));
+    assert_eq!(val["registry"].as_str(), Some("company"));
+}
+
+#[test]
+fn adds_dependency_from_new_git_index() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let index_url = create_git_repo(
+        tmpdir.path(),
+        &[(
+            "my/-p/my-package",
+            "{\"name\":\"my-package\",\"vers\":\"0.1.0\",\"yanked\":false}\n\
+             {\"name\":\"my-package\",\"vers\":\"0.2.0\",\"yanked\":false}\n",
+        )],
+    );
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!("[registries.company]\nindex = \"{}\"\n", index_url),
+    )
+    .unwrap();
+    let cargo_home = tmpdir.path().join("cargo-home");
+
+    // The index is cloned, although its default branch is not `master`.
+    execute_command_with_registries(
+        &["add", "my-package", "--registry", "company"],
+        &manifest,
+        &cargo_home,
+        &[],
+    );
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"]["version"].as_str(),
+        Some("0.2.0")
+    );
+
+    // Once cloned, the index is updated instead.
+    execute_command_with_registries(
+        &[
+            "add",
+            "my-package",
+            "--registry",
+            "company",
+            "--vers",
+            "0.1",
+        ],
+        &manifest,
+        &cargo_home,
+        &[("CARGO_EDIT_INDEX_FRESHNESS", "0")],
+    );
+}
+
+#[test]
+fn fails_to_add_from_unreachable_git_index() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let index_url = url::Url::from_directory_path(tmpdir.path().join("missing.git")).unwrap();
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!("[registries.company]\nindex = \"{}\"\n", index_url),
+    )
+    .unwrap();
+    let cargo_home = tmpdir.path().join("cargo-home");
+
+    let call = process::Command::new(get_command_path("add"))
+        .args(["add", "my-package", "--registry", "company"])
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_HOME", &cargo_home)
+        .output()
+        .unwrap();
+    assert!(!call.status.success());
+    assert!(String::from_utf8_lossy(&call.stderr).contains("Failed to fetch the registry index"));
+
+    // No empty index is left behind.
+    let indices = cargo_home.join("registry").join("index");
+    assert_eq!(fs::read_dir(indices).unwrap().count(), 0);
+}
+
+#[test]
+fn adds_dependency_from_git_index_fetched_with_cli() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let index_url = create_git_repo(
+        tmpdir.path(),
+        &[(
+            "my/-p/my-package",
+            "{\"name\":\"my-package\",\"vers\":\"0.3.0\",\"yanked\":false}\n",
+        )],
+    );
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!(
+            "[registries.company]\nindex = \"{}\"\n[net]\ngit-fetch-with-cli = true\n",
+            index_url
+        ),
+    )
+    .unwrap();
+    let cargo_home = tmpdir.path().join("cargo-home");
+
+    for _ in 0..2 {
+        execute_command_with_registries(
+            &["add", "my-package", "--registry", "company"],
+            &manifest,
+            &cargo_home,
+            &[("CARGO_EDIT_INDEX_FRESHNESS", "0")],
+        );
+    }
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-package"]["version"].as_str(),
+        Some("0.3.0")
+    );
+}
+
+#[test]
+fn fails_to_add_when_git_index_update_fails() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let index_url = create_git_repo(
+        tmpdir.path(),
+        &[(
+            "my/-p/my-package",
+            "{\"name\":\"my-package\",\"vers\":\"0.3.0\",\"yanked\":false}\n",
+        )],
+    );
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!("[registries.company]\nindex = \"{}\"\n", index_url),
+    )
+    .unwrap();
+    let cargo_home = tmpdir.path().join("cargo-home");
+    execute_command_with_registries(
+        &["add", "my-package", "--registry", "company"],
+        &manifest,
+        &cargo_home,
+        &[],
+    );
+
+    // The index is cloned already, but the registry is gone.
+    fs::remove_dir_all(tmpdir.path().join("index.git")).unwrap();
+    for fetch_with_cli in &["false", "true"] {
+        let call = process::Command::new(get_command_path("add"))
+            .args(["add", "my-package", "--registry", "company"])
+            .arg(format!("--manifest-path={}", manifest))
+            .env("CARGO_HOME", &cargo_home)
+            .env("CARGO_NET_GIT_FETCH_WITH_CLI", fetch_with_cli)
+            .env("CARGO_EDIT_INDEX_FRESHNESS", "0")
+            .output()
+            .unwrap();
+        assert!(!call.status.success());
+        assert!(
+            String::from_utf8_lossy(&call.stderr).contains("Failed to fetch the registry index")
+        );
+    }
+}
+
+#[test]
+fn skips_fetching_fresh_git_index() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let index_url = create_git_repo(
+        tmpdir.path(),
+        &[(
+            "my/-p/my-package",
+            "{\"name\":\"my-package\",\"vers\":\"0.3.0\",\"yanked\":false}\n",
+        )],
+    );
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!("[registries.company]\nindex = \"{}\"\n", index_url),
+    )
+    .unwrap();
+    let cargo_home = tmpdir.path().join("cargo-home");
+    execute_command_with_registries(
+        &["add", "my-package", "--registry", "company"],
+        &manifest,
+        &cargo_home,
+        &[],
+    );
+
+    // Were the index fetched again, it would fail.
+    fs::remove_dir_all(tmpdir.path().join("index.git")).unwrap();
+    execute_command_with_registries(
+        &["add", "my-package", "--registry", "company"],
+        &manifest,
+        &cargo_home,
+        &[],
+    );
+    execute_command_with_registries(
+        &["add", "my-package", "--registry", "company", "--no-update"],
+        &manifest,
+        &cargo_home,
+        &[("CARGO_EDIT_INDEX_FRESHNESS", "0")],
+    );
+}
+
+#[test]
+fn waits_for_package_cache_lock() {
+    use fs2::FileExt;
+
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let index_url = create_git_repo(
+        tmpdir.path(),
+        &[(
+            "my/-p/my-package",
+            "{\"name\":\"my-package\",\"vers\":\"0.3.0\",\"yanked\":false}\n",
+        )],
+    );
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!("[registries.company]\nindex = \"{}\"\n", index_url),
+    )
+    .unwrap();
+    let cargo_home = tmpdir.path().join("cargo-home");
+    fs::create_dir(&cargo_home).unwrap();
+    let lock = fs::File::create(cargo_home.join(".package-cache")).unwrap();
+    lock.lock_exclusive().unwrap();
+
+    let mut add = process::Command::new(get_command_path("add"))
+        .args(["add", "my-package", "--registry", "company"])
+        .arg(format!("--manifest-path={}", manifest))
+        .env("CARGO_HOME", &cargo_home)
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    assert!(add.try_wait().unwrap().is_none());
+
+    lock.unlock().unwrap();
+    let output = add.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Blocking waiting for file lock on package cache"));
+}
+
 #[test]
 fn adds_dependency_with_target_triple() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
@@ -869,7 +1525,7 @@ fn adds_dependency_with_target_triple() {
     let toml = get_toml(&manifest);
 
     let val = &toml["target"]["i686-unknown-linux-gnu"]["dependencies"]["my-package1"];
-    assert_eq!(val.as_str().unwrap(), "my-package1--CURRENT_VERSION_TEST");
+    assert_eq!(val.as_str().unwrap(), "0.3.1");
 }
 
 #[test]
@@ -886,7 +1542,7 @@ fn adds_dependency_with_target_cfg() {
     let toml = get_toml(&manifest);
     let val = &toml["target"]["cfg(unix)"]["dependencies"]["my-package1"];
 
-    assert_eq!(val.as_str().unwrap(), "my-package1--CURRENT_VERSION_TEST");
+    assert_eq!(val.as_str().unwrap(), "0.3.1");
 }
 
 #[test]
@@ -902,7 +1558,7 @@ fn adds_dependency_with_custom_target() {
     let toml = get_toml(&manifest);
     // Get package by hand because toml-rs does not currently handle escaping dots in get()
     let val = &toml["target"]["windows.json"]["dependencies"]["my-package1"];
-    assert_eq!(val.as_str(), Some("my-package1--CURRENT_VERSION_TEST"));
+    assert_eq!(val.as_str(), Some("0.3.1"));
 }
 
 #[test]
@@ -922,7 +1578,7 @@ fn adds_dependency_normalized_name() {
     ])
     .succeeds()
     .and()
-    .stdout()
+    .stderr()
     .contains("WARN: Added `linked-hash-map` instead of `linked_hash_map`")
     .unwrap();
 
@@ -976,6 +1632,30 @@ fn fails_to_add_multiple_optional_dev_dependencies() {
     );
 }
 
+#[test]
+fn adds_local_git_source_without_flag() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let repo_url = create_git_repo(
+        tmpdir.path(),
+        &[
+            (
+                "Cargo.toml",
+                "[package]\nname = \"my-git-package\"\nversion = \"0.1.0\"\n",
+            ),
+            ("src/lib.rs", ""),
+        ],
+    );
+
+    execute_command(&["add", &repo_url], &manifest);
+    let toml = get_toml(&manifest);
+    assert_eq!(
+        toml["dependencies"]["my-git-package"]["git"].as_str(),
+        Some(repo_url.as_str())
+    );
+
+    execute_bad_command(&["add", "file:///no/such/repository.git"], &manifest);
+}
+
 #[test]
 #[cfg(feature = "test-external-apis")]
 fn fails_to_add_inexistent_git_source_without_flag() {
@@ -1032,7 +1712,7 @@ fn overwrite_version_with_version() {
         &["add", "versioned-package"],
         r#"
 [dependencies]
-versioned-package = { version = "versioned-package--CURRENT_VERSION_TEST", optional = true }
+versioned-package = { version = "0.3.0", optional = true }
 "#,
     )
 }
@@ -1068,7 +1748,7 @@ fn overwrite_renamed() {
         &["add", "versioned-package", "--rename", "renamed"],
         r#"
 [dependencies]
-renamed = { version = "versioned-package--CURRENT_VERSION_TEST", package = "versioned-package" }
+renamed = { version = "0.3.0", package = "versioned-package" }
 "#,
     )
 }
@@ -1080,7 +1760,19 @@ fn overwrite_renamed_optional() {
         &["add", "versioned-package", "--rename", "renamed"],
         r#"
 [dependencies]
-renamed = { version = "versioned-package--CURRENT_VERSION_TEST", optional = true, package = "versioned-package" }
+renamed = { version = "0.3.0", optional = true, package = "versioned-package" }
+"#,
+    )
+}
+
+#[test]
+fn overwrite_features_are_merged() {
+    overwrite_dependency_test(
+        &["add", "tokio", "--vers", "0.2", "--features", "rt,net"],
+        &["add", "tokio", "--vers", "0.2", "--features", "macros rt"],
+        r#"
+[dependencies]
+tokio = { version = "0.2", features = ["rt", "net", "macros"] }
 "#,
     )
 }
@@ -1128,6 +1820,33 @@ versioned-package = { optional = true, path = "../foo" }
     )
 }
 
+#[test]
+fn overwrite_git_branch_with_tag() {
+    overwrite_dependency_test(
+        &[
+            "add",
+            "versioned-package",
+            "--git",
+            "git://git.git",
+            "--branch",
+            "dev",
+            "--optional",
+        ],
+        &[
+            "add",
+            "versioned-package",
+            "--git",
+            "git://git.git",
+            "--tag",
+            "v1.0",
+        ],
+        r#"
+[dependencies]
+versioned-package = { git = "git://git.git", optional = true, tag = "v1.0" }
+"#,
+    )
+}
+
 #[test]
 fn overwrite_path_with_version() {
     overwrite_dependency_test(
@@ -1135,7 +1854,7 @@ fn overwrite_path_with_version() {
         &["add", "versioned-package"],
         r#"
 [dependencies]
-versioned-package = "versioned-package--CURRENT_VERSION_TEST"
+versioned-package = "0.3.0"
 "#,
     )
 }
@@ -1175,7 +1894,8 @@ For more information try --help",
 
 #[test]
 fn add_prints_message() {
-    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
 
     assert_cli::Assert::command(&[
         get_command_path("add").as_str(),
@@ -1193,7 +1913,8 @@ fn add_prints_message() {
 
 #[test]
 fn add_prints_message_with_section() {
-    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
 
     assert_cli::Assert::command(&[
         get_command_path("add").as_str(),
@@ -1213,7 +1934,8 @@ fn add_prints_message_with_section() {
 
 #[test]
 fn add_prints_message_for_dev_deps() {
-    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
 
     assert_cli::Assert::command(&[
         get_command_path("add").as_str(),
@@ -1233,7 +1955,8 @@ fn add_prints_message_for_dev_deps() {
 
 #[test]
 fn add_prints_message_for_build_deps() {
-    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
 
     assert_cli::Assert::command(&[
         get_command_path("add").as_str(),
@@ -1288,8 +2011,132 @@ version = "0.0.0"
 
 [dependencies]
 atty = "0.2.13"
-toml = "toml--CURRENT_VERSION_TEST"
+toml = "0.5.3"
 toml_edit = "0.1.5"
 "#
     );
 }
+
+/// Copy the root manifest of the workspace test and the manifest of its member `one` into a
+/// temporary directory.
+fn copy_workspace_member() -> (tempdir::TempDir, String, String) {
+    let tmpdir = tempdir::TempDir::new("cargo-add-workspace-test")
+        .expect("failed to construct temporary directory");
+    fs::create_dir(tmpdir.path().join("one")).unwrap();
+    let copy_in = |file: &str| {
+        let path = tmpdir.path().join(file);
+        fs::copy(format!("tests/fixtures/workspace/{}", file), &path)
+            .unwrap_or_else(|err| panic!("could not copy test file: {}", err));
+        path.to_str().unwrap().to_string()
+    };
+
+    let root = copy_in("Cargo.toml");
+    let member = copy_in("one/Cargo.toml");
+    (tmpdir, root, member)
+}
+
+#[test]
+fn adds_workspace_dependency() {
+    let (_tmpdir, root, member) = copy_workspace_member();
+
+    execute_command(
+        &["add", "--workspace-dep", "regex", "--features", "unicode"],
+        &member,
+    );
+
+    let toml = get_toml(&root);
+    assert_eq!(
+        toml["workspace"]["dependencies"]["regex"].as_str(),
+        Some("1.3.1")
+    );
+    let toml = get_toml(&member);
+    assert!(toml
+        .to_string()
+        .contains(r#"regex = { workspace = true, features = ["unicode"] }"#));
+}
+
+#[test]
+fn adds_workspace_dependency_replacing_member_version() {
+    let (_tmpdir, root, member) = copy_workspace_member();
+
+    execute_command(
+        &[
+            "add",
+            "--workspace-dep",
+            "libc",
+            "--vers",
+            "0.2.60",
+            "--optional",
+        ],
+        &member,
+    );
+
+    let toml = get_toml(&root);
+    assert_eq!(
+        toml["workspace"]["dependencies"]["libc"].as_str(),
+        Some("0.2.60")
+    );
+    let toml = get_toml(&member);
+    assert!(toml
+        .to_string()
+        .contains("libc = { workspace = true, optional = true }"));
+}
+
+#[test]
+fn adds_workspace_path_dependency_relative_to_root() {
+    let (_tmpdir, root, member) = copy_workspace_member();
+
+    execute_command(
+        &[
+            "add",
+            "--workspace-dep",
+            "four",
+            "--path",
+            "../explicit/four",
+        ],
+        &member,
+    );
+
+    let toml = get_toml(&root);
+    assert_eq!(
+        toml["workspace"]["dependencies"]["four"]["path"].as_str(),
+        Some("explicit/four")
+    );
+}
+
+#[test]
+fn adds_workspace_dependency_to_virtual_root() {
+    let (tmpdir, root, _member) = copy_workspace_member();
+    setup_test_index(tmpdir.path());
+
+    assert_cli::Assert::command(&[
+        get_command_path("add").as_str(),
+        "add",
+        "--workspace-dep",
+        "docopt",
+        "--vers=0.8.0",
+        &format!("--manifest-path={}", root),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Adding docopt v0.8.0 to workspace.dependencies")
+    .unwrap();
+
+    let toml = get_toml(&root);
+    assert_eq!(
+        toml["workspace"]["dependencies"]["docopt"].as_str(),
+        Some("0.8.0")
+    );
+    assert!(toml["dependencies"].is_none());
+}
+
+#[test]
+fn fails_to_add_to_virtual_root_without_workspace_dep() {
+    let (_tmpdir, root, _member) = copy_workspace_member();
+
+    execute_bad_command(&["add", "docopt", "--vers=0.8.0"], &root);
+
+    let toml = get_toml(&root);
+    assert!(toml["dependencies"].is_none());
+}
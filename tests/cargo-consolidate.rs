@@ -0,0 +1,168 @@
+#[macro_use]
+extern crate pretty_assertions;
+
+use std::process;
+
+mod utils;
+use crate::utils::{copy_workspace_test, execute_command, get_command_path, get_toml};
+
+fn assert_inherits(manifest: &str, name: &str) {
+    let dep = &get_toml(manifest)["dependencies"][name];
+    assert_eq!(dep["workspace"].as_bool(), Some(true));
+    assert!(dep["version"].is_none());
+}
+
+#[test]
+fn consolidate_shared_dependencies() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+
+    execute_command(&["consolidate"], &root_manifest);
+
+    // All of the workspace members have `libc` as a dependency.
+    assert_eq!(
+        get_toml(&root_manifest)["workspace"]["dependencies"]["libc"].as_str(),
+        Some("0.2.28")
+    );
+    for workspace_member in &workspace_manifests {
+        assert_inherits(workspace_member, "libc");
+    }
+
+    // `rand` is required as `0.3` and `0.2`, which cannot be unified.
+    assert!(get_toml(&root_manifest)["workspace"]["dependencies"]["rand"].is_none());
+    assert_eq!(
+        get_toml(&workspace_manifests[0])["dependencies"]["rand"].as_str(),
+        Some("0.3")
+    );
+}
+
+#[test]
+fn consolidate_unifies_requirements_and_keeps_member_keys() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+    execute_command(
+        &[
+            "add",
+            "libc",
+            "--vers",
+            "0.2.62",
+            "--optional",
+            "--features",
+            "extra_traits",
+        ],
+        &workspace_manifests[0],
+    );
+
+    execute_command(&["consolidate"], &root_manifest);
+
+    assert_eq!(
+        get_toml(&root_manifest)["workspace"]["dependencies"]["libc"].as_str(),
+        Some("0.2.62")
+    );
+    let one = get_toml(&workspace_manifests[0]);
+    let libc = &one["dependencies"]["libc"];
+    assert_eq!(libc["workspace"].as_bool(), Some(true));
+    assert_eq!(libc["optional"].as_bool(), Some(true));
+    assert_eq!(
+        libc["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .next()
+            .unwrap()
+            .as_str(),
+        Some("extra_traits")
+    );
+    assert!(libc["version"].is_none());
+    assert_inherits(&workspace_manifests[1], "libc");
+}
+
+#[test]
+fn consolidate_respects_min_members() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+
+    execute_command(&["consolidate", "--min-members", "5"], &root_manifest);
+
+    assert!(get_toml(&root_manifest)["workspace"]["dependencies"].is_none());
+    assert_eq!(
+        get_toml(&workspace_manifests[0])["dependencies"]["libc"].as_str(),
+        Some("0.2.28")
+    );
+}
+
+#[test]
+fn consolidate_dry_run_prints_diff() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+
+    let output = process::Command::new(get_command_path("consolidate"))
+        .args([
+            "consolidate",
+            "--dry-run",
+            "--manifest-path",
+            &root_manifest,
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Consolidating libc v0.2.28 (4 members)"));
+    assert!(stdout.contains("Skipping rand: incompatible requirements 0.3, 0.2"));
+    assert!(stdout.contains("@@ -4,3 +4,6 @@\n     \"two\",\n"));
+    assert!(stdout.contains("+[workspace.dependencies]\n+libc = \"0.2.28\""));
+    assert!(stdout.contains("-libc = \"0.2.28\"\n+libc = { workspace = true }"));
+
+    // Nothing was written.
+    assert!(get_toml(&root_manifest)["workspace"]["dependencies"].is_none());
+    assert_eq!(
+        get_toml(&workspace_manifests[0])["dependencies"]["libc"].as_str(),
+        Some("0.2.28")
+    );
+}
+
+#[test]
+fn consolidate_keeps_existing_workspace_entry() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+    let mut root = std::fs::read_to_string(&root_manifest).unwrap();
+    root.push_str("\n[workspace.dependencies]\nlibc = { version = \"0.2\", path = \"libc\" }\n");
+    std::fs::write(&root_manifest, root).unwrap();
+
+    execute_command(&["consolidate"], &root_manifest);
+
+    let root = get_toml(&root_manifest);
+    let libc = &root["workspace"]["dependencies"]["libc"];
+    assert_eq!(libc["version"].as_str(), Some("0.2.28"));
+    assert_eq!(libc["path"].as_str(), Some("libc"));
+    for workspace_member in &workspace_manifests {
+        assert_inherits(workspace_member, "libc");
+    }
+}
+
+#[test]
+fn consolidate_skips_key_for_different_packages() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+    execute_command(
+        &["add", "serde_json", "--vers", "1.0", "--rename", "json"],
+        &workspace_manifests[0],
+    );
+    let mut two = std::fs::read_to_string(&workspace_manifests[1]).unwrap();
+    two = two.replace("[dependencies]\n", "[dependencies]\njson = \"0.12\"\n");
+    std::fs::write(&workspace_manifests[1], two).unwrap();
+
+    let output = process::Command::new(get_command_path("consolidate"))
+        .args(["consolidate", "--manifest-path", &root_manifest])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout
+        .contains("Skipping json: members use the key for different packages json, serde_json"));
+
+    assert!(get_toml(&root_manifest)["workspace"]["dependencies"]["json"].is_none());
+    assert_eq!(
+        get_toml(&workspace_manifests[0])["dependencies"]["json"]["package"].as_str(),
+        Some("serde_json")
+    );
+    assert_eq!(
+        get_toml(&workspace_manifests[1])["dependencies"]["json"].as_str(),
+        Some("0.12")
+    );
+}
@@ -57,6 +57,140 @@ fn remove_multiple_existing_dependencies_from_specific_section() {
     assert!(toml["dev-dependencies"].is_none());
 }
 
+#[test]
+fn remove_existing_dependency_from_target_section() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    let toml = get_toml(&manifest);
+    assert!(!toml["target"]["cfg(unix)"]["dependencies"]["openssl"].is_none());
+    execute_command(&["rm", "--target", "cfg(unix)", "openssl"], &manifest);
+    let toml = get_toml(&manifest);
+    assert!(toml["target"]["cfg(unix)"].is_none());
+    assert!(!toml["target"]["cfg(windows)"].is_none());
+
+    // Test removing target-specific dev dependency.
+    execute_command(
+        &["rm", "--target", "cfg(windows)", "--dev", "regex"],
+        &manifest,
+    );
+    let toml = get_toml(&manifest);
+    assert!(toml["target"]["cfg(windows)"]["dev-dependencies"].is_none());
+    assert!(!toml["target"]["cfg(windows)"]["dependencies"]["winapi"].is_none());
+    assert!(!toml["dev-dependencies"]["regex"].is_none());
+
+    execute_command(&["rm", "--target", "cfg(windows)", "winapi"], &manifest);
+    let toml = get_toml(&manifest);
+    assert!(toml["target"].is_none());
+}
+
+#[test]
+fn empty_target_section() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "openssl",
+        "--target",
+        "",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains("Command failed due to unhandled error: Target specification may not be empty")
+    .unwrap();
+}
+
+#[test]
+fn invalid_target_section() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "openssl",
+        "--target",
+        "cfg(windows)",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains(
+        "Command failed due to unhandled error: The dependency `openssl` could not be found in \
+         `target.cfg(windows).dependencies`.",
+    )
+    .unwrap();
+}
+
+#[test]
+fn remove_dependency_from_all_sections() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    let toml = get_toml(&manifest);
+    assert!(!toml["dependencies"]["semver"].is_none());
+    assert!(!toml["build-dependencies"]["semver"].is_none());
+    assert!(!toml["dev-dependencies"]["regex"].is_none());
+    assert!(!toml["target"]["cfg(windows)"]["dev-dependencies"]["regex"].is_none());
+
+    execute_command(&["rm", "--all-sections", "semver", "regex"], &manifest);
+
+    let toml = get_toml(&manifest);
+    assert!(toml["dependencies"]["semver"].is_none());
+    assert!(toml["build-dependencies"].is_none());
+    assert!(toml["dev-dependencies"]["regex"].is_none());
+    assert!(toml["target"]["cfg(windows)"]["dev-dependencies"].is_none());
+    assert!(!toml["target"]["cfg(windows)"]["dependencies"]["winapi"].is_none());
+    assert!(!toml["dependencies"]["docopt"].is_none());
+}
+
+#[test]
+fn invalid_dependency_in_all_sections() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "--all-sections",
+        "invalid_dependency_name",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .fails_with(1)
+    .and()
+    .stderr()
+    .contains(
+        "Command failed due to unhandled error: The dependency `invalid_dependency_name` could \
+         not be found in any dependency table.",
+    )
+    .unwrap();
+}
+
+#[test]
+fn remove_feature_references_to_removed_dependency() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    execute_command(&["rm", "clippy"], &manifest);
+
+    let toml = get_toml(&manifest);
+    assert!(toml["features"]["lint"].is_none());
+    assert_eq!(
+        toml["features"]["nightly"].as_array().unwrap().len(),
+        1,
+        "only `semver/nightly` should be left"
+    );
+
+    // `semver` is still a build dependency, so its features stay valid.
+    execute_command(&["rm", "semver"], &manifest);
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["features"]["nightly"].as_array().unwrap().len(), 1);
+
+    execute_command(&["rm", "--build", "semver"], &manifest);
+    let toml = get_toml(&manifest);
+    assert!(toml["features"]["nightly"].is_none());
+    assert!(!toml["features"]["default"].is_none());
+}
+
 #[test]
 fn remove_section_after_removed_last_dependency() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
@@ -208,6 +342,63 @@ fn rm_prints_message() {
     .unwrap();
 }
 
+#[test]
+fn rm_prints_message_with_target() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "openssl",
+        "--target=cfg(unix)",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .is("Removing openssl from dependencies for target `cfg(unix)`")
+    .unwrap();
+}
+
+#[test]
+fn rm_prints_messages_for_all_sections() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "regex",
+        "--all-sections",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .is("Removing regex from dev-dependencies\n    \
+         Removing regex from dev-dependencies for target `cfg(windows)`")
+    .unwrap();
+}
+
+#[test]
+fn rm_prints_messages_for_features() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
+
+    assert_cli::Assert::command(&[
+        get_command_path("rm").as_str(),
+        "rm",
+        "clippy",
+        &format!("--manifest-path={}", manifest),
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .is("Removing clippy from dependencies\n    \
+         Removing clippy from feature lint\n    \
+         Removing feature lint\n    \
+         Removing lint from feature nightly")
+    .unwrap();
+}
+
 #[test]
 fn rm_prints_messages_for_multiple() {
     let (_tmpdir, manifest) = clone_out_test("tests/fixtures/rm/Cargo.toml.sample");
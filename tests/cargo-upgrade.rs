@@ -1,63 +1,14 @@
 #[macro_use]
 extern crate pretty_assertions;
 
-use std::{fs, path::Path};
+use std::{fs, path::Path, process};
 
 mod utils;
 use crate::utils::{
-    clone_out_test, execute_command, execute_command_in_dir, get_command_path, get_toml,
-    setup_alt_registry_config,
+    clone_out_test, copy_workspace_test, create_git_repo, execute_command, execute_command_in_dir,
+    get_command_path, get_toml, setup_alt_registry_config, setup_test_index,
 };
 
-/// Helper function that copies the workspace test into a temporary directory.
-pub fn copy_workspace_test() -> (tempdir::TempDir, String, Vec<String>) {
-    // Create a temporary directory and copy in the root manifest, the dummy rust file, and
-    // workspace member manifests.
-    let tmpdir = tempdir::TempDir::new("upgrade_workspace")
-        .expect("failed to construct temporary directory");
-
-    let (root_manifest_path, workspace_manifest_paths) = {
-        // Helper to copy in files to the temporary workspace. The standard library doesn't have a
-        // good equivalent of `cp -r`, hence this oddity.
-        let copy_in = |dir, file| {
-            let file_path = tmpdir
-                .path()
-                .join(dir)
-                .join(file)
-                .to_str()
-                .unwrap()
-                .to_string();
-
-            fs::create_dir_all(tmpdir.path().join(dir)).unwrap();
-
-            fs::copy(
-                format!("tests/fixtures/workspace/{}/{}", dir, file),
-                &file_path,
-            )
-            .unwrap_or_else(|err| panic!("could not copy test file: {}", err));
-
-            file_path
-        };
-
-        let root_manifest_path = copy_in(".", "Cargo.toml");
-        copy_in(".", "dummy.rs");
-        copy_in(".", "Cargo.lock");
-
-        let workspace_manifest_paths = ["one", "two", "implicit/three", "explicit/four"]
-            .iter()
-            .map(|member| copy_in(member, "Cargo.toml"))
-            .collect::<Vec<_>>();
-
-        (root_manifest_path, workspace_manifest_paths)
-    };
-
-    (
-        tmpdir,
-        root_manifest_path,
-        workspace_manifest_paths.to_owned(),
-    )
-}
-
 // Verify that an upgraded Cargo.toml matches what we expect.
 #[test]
 fn upgrade_as_expected() {
@@ -84,7 +35,271 @@ fn upgrade_all() {
     // Verify that `docopt` has been updated successfully.
     assert_eq!(
         get_toml(&manifest)["dependencies"]["docopt"].as_str(),
-        Some("docopt--CURRENT_VERSION_TEST")
+        Some("1.1.0")
+    );
+}
+
+#[test]
+fn upgrade_preserves_requirement_format() {
+    let (_tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    execute_command(&["add", "docopt", "--vers", "~0.8"], &manifest);
+    execute_command(&["add", "libc", "--vers", "=0.2.28"], &manifest);
+    execute_command(&["add", "ftp", "--vers", "2"], &manifest);
+    execute_command(&["add", "syn", "--vers", ">=0.11, <1"], &manifest);
+
+    execute_command(&["upgrade", "--pinned"], &manifest);
+
+    let dependencies = &get_toml(&manifest)["dependencies"];
+    assert_eq!(dependencies["docopt"].as_str(), Some("~1.1"));
+    assert_eq!(dependencies["libc"].as_str(), Some("=0.2.65"));
+    assert_eq!(dependencies["ftp"].as_str(), Some("3"));
+    assert_eq!(dependencies["syn"].as_str(), Some(">=0.11, <2"));
+}
+
+#[test]
+fn upgrade_skips_pinned() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
+    execute_command(&["add", "docopt", "--vers", "0.8.0"], &manifest);
+    execute_command(&["add", "libc", "--vers", "=0.2.28"], &manifest);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--manifest-path",
+        &manifest,
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("    libc (= 0.2.28): pinned, skipped")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["docopt"].as_str(), Some("1.1.0"));
+    assert_eq!(toml["dependencies"]["libc"].as_str(), Some("=0.2.28"));
+
+    // A pinned dependency is upgraded when given a version.
+    execute_command(&["upgrade", "libc@=0.2.62"], &manifest);
+    assert_eq!(
+        get_toml(&manifest)["dependencies"]["libc"].as_str(),
+        Some("=0.2.62")
+    );
+}
+
+#[test]
+fn upgrade_skips_excluded_and_ignored() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
+    execute_command(&["add", "docopt", "--vers", "0.8.0"], &manifest);
+    execute_command(&["add", "libc", "--vers", "0.2.28"], &manifest);
+    execute_command(&["add", "ftp", "--vers", "2.2.1"], &manifest);
+    let mut contents = fs::read_to_string(&manifest).unwrap();
+    contents.push_str("\n[package.metadata.cargo-edit]\nignore = [\"ftp\"]\n");
+    fs::write(&manifest, contents).unwrap();
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--exclude",
+        "libc",
+        "--manifest-path",
+        &manifest,
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("    ftp (^2.2.1): ignored in `package.metadata.cargo-edit`, skipped")
+    .and()
+    .stdout()
+    .contains("    libc (^0.2.28): excluded, skipped")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["docopt"].as_str(), Some("1.1.0"));
+    assert_eq!(toml["dependencies"]["libc"].as_str(), Some("0.2.28"));
+    assert_eq!(toml["dependencies"]["ftp"].as_str(), Some("2.2.1"));
+
+    // An ignored dependency is upgraded when asked for.
+    execute_command(&["upgrade", "ftp"], &manifest);
+    assert_eq!(
+        get_toml(&manifest)["dependencies"]["ftp"].as_str(),
+        Some("3.0.1")
+    );
+}
+
+#[test]
+fn upgrade_compatible() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
+    execute_command(&["add", "docopt", "--vers", "0.8.0"], &manifest);
+    execute_command(&["add", "libc", "--vers", "0.2.28"], &manifest);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--compatible",
+        "--manifest-path",
+        &manifest,
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("Incompatible releases available, not upgraded to:\n    docopt (^0.8.0): 1.1.0")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["docopt"].as_str(), Some("0.8.3"));
+    assert_eq!(toml["dependencies"]["libc"].as_str(), Some("0.2.65"));
+}
+
+#[test]
+fn upgrade_incompatible() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
+    execute_command(&["add", "docopt", "--vers", "0.8.0"], &manifest);
+    execute_command(&["add", "libc", "--vers", "0.2.28"], &manifest);
+
+    assert_cli::Assert::command(&[
+        get_command_path("upgrade").as_str(),
+        "upgrade",
+        "--incompatible",
+        "--manifest-path",
+        &manifest,
+    ])
+    .succeeds()
+    .and()
+    .stdout()
+    .contains("docopt v0.8.0 -> v1.1.0 (major)")
+    .unwrap();
+
+    let toml = get_toml(&manifest);
+    assert_eq!(toml["dependencies"]["docopt"].as_str(), Some("1.1.0"));
+    assert_eq!(toml["dependencies"]["libc"].as_str(), Some("0.2.28"));
+}
+
+#[test]
+fn upgrade_with_json_messages() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    setup_test_index(tmpdir.path());
+    execute_command(&["add", "docopt", "--vers", "0.8.0"], &manifest);
+    execute_command(&["add", "libc", "--vers", "0.2.28"], &manifest);
+
+    let output = process::Command::new(get_command_path("upgrade"))
+        .args(["upgrade", "--dry-run", "--message-format", "json"])
+        .args(["--manifest-path", &manifest])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let mut messages = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .map(|message| {
+            (
+                message["crate"].as_str().unwrap().to_owned(),
+                message["from"].as_str().unwrap().to_owned(),
+                message["to"].as_str().unwrap().to_owned(),
+                message["kind"].as_str().unwrap().to_owned(),
+            )
+        })
+        .collect::<Vec<_>>();
+    messages.sort();
+    assert_eq!(
+        messages,
+        vec![
+            (
+                "docopt".into(),
+                "0.8.0".into(),
+                "1.1.0".into(),
+                "major".into()
+            ),
+            (
+                "libc".into(),
+                "0.2.28".into(),
+                "0.2.65".into(),
+                "minor".into()
+            ),
+        ]
+    );
+    // Nothing is written in a dry run.
+    assert_eq!(
+        get_toml(&manifest)["dependencies"]["docopt"].as_str(),
+        Some("0.8.0")
+    );
+}
+
+#[test]
+fn upgrade_with_json_messages_from_git_index() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/add/Cargo.toml.sample");
+    let index_url = create_git_repo(
+        tmpdir.path(),
+        &[
+            (
+                "ot/he/other-package",
+                "{\"name\":\"other-package\",\"vers\":\"1.0.0\",\"yanked\":false}\n\
+                 {\"name\":\"other-package\",\"vers\":\"1.2.0\",\"yanked\":false}\n",
+            ),
+            (
+                "my/-p/my-package",
+                "{\"name\":\"my-package\",\"vers\":\"0.3.0\",\"yanked\":false}\n\
+                 {\"name\":\"my-package\",\"vers\":\"0.4.0\",\"yanked\":false}\n",
+            ),
+            (
+                "pi/nn/pinned-package",
+                "{\"name\":\"pinned-package\",\"vers\":\"0.1.0\",\"yanked\":false}\n\
+                 {\"name\":\"pinned-package\",\"vers\":\"0.2.0\",\"yanked\":false}\n",
+            ),
+        ],
+    );
+    fs::create_dir(tmpdir.path().join(".cargo")).unwrap();
+    fs::write(
+        tmpdir.path().join(".cargo").join("config"),
+        format!(
+            "[source.crates-io]\nreplace-with = \"company\"\n\
+             [source.company]\nregistry = \"{}\"\n",
+            index_url
+        ),
+    )
+    .unwrap();
+    let mut contents = fs::read_to_string(&manifest).unwrap();
+    contents.push_str(
+        "\n[dependencies]\n\
+         other-package = \"1.0.0\"\n\
+         my-package = \"0.3.0\"\n\
+         pinned-package = \"=0.1.0\"\n",
+    );
+    fs::write(&manifest, contents).unwrap();
+
+    let output = process::Command::new(get_command_path("upgrade"))
+        .args(["upgrade", "--compatible", "--message-format", "json"])
+        .env("CARGO_HOME", tmpdir.path().join("cargo-home"))
+        .current_dir(tmpdir.path())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    // The index was fetched, but its status went to stderr.
+    assert!(stderr.contains("Initializing"), "{}", stderr);
+
+    let mut messages = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .map(|mut message| {
+            message.as_object_mut().unwrap().remove("manifest");
+            message.to_string()
+        })
+        .collect::<Vec<_>>();
+    messages.sort();
+    assert_eq!(
+        messages,
+        vec![
+            r#"{"crate":"my-package","from":"^0.3.0","incompatible":"0.4.0"}"#,
+            r#"{"crate":"other-package","from":"1.0.0","kind":"minor","to":"1.2.0"}"#,
+            r#"{"crate":"pinned-package","from":"= 0.1.0","skipped":"pinned"}"#,
+        ]
     );
 }
 
@@ -101,7 +316,7 @@ fn upgrade_all_allow_prerelease() {
     // Verify that `docopt` has been updated successfully.
     assert_eq!(
         get_toml(&manifest)["dependencies"]["docopt"].as_str(),
-        Some("docopt--PRERELEASE_VERSION_TEST")
+        Some("1.2.0-alpha.1")
     );
 }
 
@@ -118,7 +333,7 @@ fn upgrade_prereleased_without_the_flag() {
     // Verify that `b` has been updated successfully to a prerelease version.
     assert_eq!(
         get_toml(&manifest)["dependencies"]["b"].as_str(),
-        Some("b--PRERELEASE_VERSION_TEST")
+        Some("0.9.0-beta.1")
     );
 }
 
@@ -136,12 +351,12 @@ fn upgrade_prerelease_already_prereleased() {
     // Verify that `a` has been updated successfully to a stable version.
     assert_eq!(
         get_toml(&manifest)["dependencies"]["a"].as_str(),
-        Some("a--CURRENT_VERSION_TEST")
+        Some("1.2.0")
     );
     // Verify that `b` has been updated successfully to a prerelease version.
     assert_eq!(
         get_toml(&manifest)["dependencies"]["b"].as_str(),
-        Some("b--PRERELEASE_VERSION_TEST")
+        Some("0.9.0-beta.1")
     );
 }
 
@@ -192,10 +407,7 @@ fn upgrade_specified_only() {
 
     // Verify that `docopt` was upgraded, but not `env_proxy`
     let dependencies = &get_toml(&manifest)["dependencies"];
-    assert_eq!(
-        dependencies["docopt"].as_str(),
-        Some("docopt--CURRENT_VERSION_TEST")
-    );
+    assert_eq!(dependencies["docopt"].as_str(), Some("1.1"));
     assert_eq!(dependencies["env_proxy"].as_str(), Some("0.1.1"));
 }
 
@@ -226,10 +438,7 @@ fn upgrade_optional_dependency() {
     // Dependency present afterwards - correct version, and still optional.
     let toml = get_toml(&manifest);
     let val = &toml["dependencies"]["docopt"];
-    assert_eq!(
-        val["version"].as_str(),
-        Some("docopt--CURRENT_VERSION_TEST")
-    );
+    assert_eq!(val["version"].as_str(), Some(">=1.1.0"));
     assert_eq!(val["optional"].as_bool(), Some(true));
 }
 
@@ -242,16 +451,10 @@ fn upgrade_renamed_dependency_all() {
     let toml = get_toml(&manifest);
 
     let dep1 = &toml["dependencies"]["te"];
-    assert_eq!(
-        dep1["version"].as_str(),
-        Some("toml_edit--CURRENT_VERSION_TEST")
-    );
+    assert_eq!(dep1["version"].as_str(), Some("0.2.0"));
 
     let dep2 = &toml["dependencies"]["rx"];
-    assert_eq!(
-        dep2["version"].as_str(),
-        Some("regex--CURRENT_VERSION_TEST")
-    );
+    assert_eq!(dep2["version"].as_str(), Some("1.3"));
 }
 
 #[test]
@@ -262,10 +465,7 @@ fn upgrade_renamed_dependency_inline_specified_only() {
 
     let toml = get_toml(&manifest);
     let dep = &toml["dependencies"]["te"];
-    assert_eq!(
-        dep["version"].as_str(),
-        Some("toml_edit--CURRENT_VERSION_TEST")
-    );
+    assert_eq!(dep["version"].as_str(), Some("0.2.0"));
 }
 
 #[test]
@@ -276,7 +476,7 @@ fn upgrade_renamed_dependency_table_specified_only() {
 
     let toml = get_toml(&manifest);
     let dep = &toml["dependencies"]["rx"];
-    assert_eq!(dep["version"].as_str(), Some("regex--CURRENT_VERSION_TEST"));
+    assert_eq!(dep["version"].as_str(), Some("1.3"));
 }
 
 #[test]
@@ -293,17 +493,11 @@ fn upgrade_alt_registry_dependency_all() {
     let toml = get_toml(&manifest);
 
     let dep1 = &toml["dependencies"]["toml_edit"];
-    assert_eq!(
-        dep1["version"].as_str(),
-        Some("toml_edit--CURRENT_VERSION_TEST")
-    );
+    assert_eq!(dep1["version"].as_str(), Some("0.2.0"));
     assert_eq!(dep1["registry"].as_str(), Some("alternative"));
 
     let dep2 = &toml["dependencies"]["regex"];
-    assert_eq!(
-        dep2["version"].as_str(),
-        Some("regex--CURRENT_VERSION_TEST")
-    );
+    assert_eq!(dep2["version"].as_str(), Some("1.3"));
     assert_eq!(dep2["registry"].as_str(), Some("alternative"));
 }
 
@@ -316,10 +510,7 @@ fn upgrade_alt_registry_dependency_inline_specified_only() {
 
     let toml = get_toml(&manifest);
     let dep = &toml["dependencies"]["toml_edit"];
-    assert_eq!(
-        dep["version"].as_str(),
-        Some("toml_edit--CURRENT_VERSION_TEST")
-    );
+    assert_eq!(dep["version"].as_str(), Some("0.2.0"));
     assert_eq!(dep["registry"].as_str(), Some("alternative"));
 }
 
@@ -332,7 +523,7 @@ fn upgrade_alt_registry_dependency_table_specified_only() {
 
     let toml = get_toml(&manifest);
     let dep = &toml["dependencies"]["regex"];
-    assert_eq!(dep["version"].as_str(), Some("regex--CURRENT_VERSION_TEST"));
+    assert_eq!(dep["version"].as_str(), Some("1.3"));
     assert_eq!(dep["registry"].as_str(), Some("alternative"));
 }
 
@@ -363,9 +554,101 @@ fn upgrade_workspace() {
     for workspace_member in workspace_manifests {
         assert_eq!(
             get_toml(&workspace_member)["dependencies"]["libc"].as_str(),
-            Some("libc--CURRENT_VERSION_TEST")
+            Some("0.2.65")
+        );
+    }
+}
+
+#[test]
+fn upgrade_workspace_members_requiring_different_versions() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+    execute_command(
+        &["add", "docopt", "--vers", "0.8.0"],
+        &workspace_manifests[0],
+    );
+    execute_command(
+        &["add", "docopt", "--vers", "1.0.0"],
+        &workspace_manifests[1],
+    );
+    let docopt = |manifest: &str| {
+        get_toml(manifest)["dependencies"]["docopt"]
+            .as_str()
+            .map(String::from)
+    };
+
+    // Each member is upgraded within its own requirement.
+    execute_command(&["upgrade", "--all", "--compatible"], &root_manifest);
+    assert_eq!(docopt(&workspace_manifests[0]).as_deref(), Some("0.8.3"));
+    assert_eq!(docopt(&workspace_manifests[1]).as_deref(), Some("1.1.0"));
+
+    // Only the member whose requirement doesn't match the latest release is upgraded.
+    execute_command(&["upgrade", "--all", "--incompatible"], &root_manifest);
+    assert_eq!(docopt(&workspace_manifests[0]).as_deref(), Some("1.1.0"));
+    assert_eq!(docopt(&workspace_manifests[1]).as_deref(), Some("1.1.0"));
+}
+
+/// Make the members `one` and `two` of the workspace test inherit `libc` from the workspace root.
+fn inherit_libc(workspace_manifests: &[String]) {
+    for member in &workspace_manifests[..2] {
+        execute_command(
+            &["add", "--workspace-dep", "libc", "--vers", "0.2.28"],
+            member,
+        );
+    }
+}
+
+fn assert_inherits_libc(manifest: &str) {
+    let libc = &get_toml(manifest)["dependencies"]["libc"];
+    assert_eq!(libc["workspace"].as_bool(), Some(true));
+    assert!(libc["version"].is_none());
+}
+
+#[test]
+fn upgrade_workspace_inherited() {
+    let (tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+    setup_test_index(tmpdir.path());
+    inherit_libc(&workspace_manifests);
+
+    let output = process::Command::new(get_command_path("upgrade"))
+        .args(["upgrade", "--all", "--manifest-path", &root_manifest])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    assert_eq!(
+        get_toml(&root_manifest)["workspace"]["dependencies"]["libc"].as_str(),
+        Some("0.2.65")
+    );
+    assert_inherits_libc(&workspace_manifests[0]);
+    assert_inherits_libc(&workspace_manifests[1]);
+    for workspace_member in &workspace_manifests[2..] {
+        assert_eq!(
+            get_toml(workspace_member)["dependencies"]["libc"].as_str(),
+            Some("0.2.65")
         );
     }
+
+    // The root entry is upgraded once, the members `three` and `four` once each.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("libc v0.2.28 -> v").count(), 3);
+}
+
+#[test]
+fn upgrade_member_inherited() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+    inherit_libc(&workspace_manifests);
+
+    execute_command(&["upgrade", "libc"], &workspace_manifests[0]);
+
+    assert_eq!(
+        get_toml(&root_manifest)["workspace"]["dependencies"]["libc"].as_str(),
+        Some("0.2.65")
+    );
+    assert_inherits_libc(&workspace_manifests[0]);
+    assert_eq!(
+        get_toml(&workspace_manifests[2])["dependencies"]["libc"].as_str(),
+        Some("0.2.28")
+    );
 }
 
 /// Detect if attempting to run against a workspace root and give a helpful warning.
@@ -449,7 +732,7 @@ fn unknown_flags() {
         "error: Found argument '--flag' which wasn't expected, or isn't valid in this context
 
 USAGE:
-    cargo upgrade [FLAGS] [OPTIONS] [dependency]...
+    cargo upgrade [FLAGS] [OPTIONS] [--] [dependency]...
 
 For more information try --help ",
     )
@@ -473,6 +756,24 @@ fn upgrade_to_lockfile() {
     assert_eq!(target.to_string(), upgraded.to_string());
 }
 
+#[test]
+fn upgrade_to_lockfile_excluded() {
+    let (tmpdir, manifest) = clone_out_test("tests/fixtures/upgrade/Cargo.toml.lockfile_source");
+    fs::copy(
+        Path::new("tests/fixtures/upgrade/Cargo.lock"),
+        tmpdir.path().join("Cargo.lock"),
+    )
+    .unwrap_or_else(|err| panic!("could not copy test lock file: {}", err));
+    execute_command(
+        &["upgrade", "--to-lockfile", "--exclude", "rand"],
+        &manifest,
+    );
+
+    let dependencies = &get_toml(&manifest)["dependencies"];
+    assert_eq!(dependencies["libc"].as_str(), Some("0.2.65"));
+    assert_eq!(dependencies["rand"].as_str(), Some("0.3"));
+}
+
 #[test]
 fn upgrade_workspace_to_lockfile() {
     let (tmpdir, root_manifest, _workspace_manifests) = copy_workspace_test();
@@ -490,6 +791,21 @@ fn upgrade_workspace_to_lockfile() {
     assert_eq!(two_target.to_string(), two_upgraded.to_string());
 }
 
+#[test]
+fn upgrade_workspace_inherited_to_lockfile() {
+    let (_tmpdir, root_manifest, workspace_manifests) = copy_workspace_test();
+    inherit_libc(&workspace_manifests);
+
+    execute_command(&["upgrade", "--all", "--to-lockfile"], &root_manifest);
+
+    assert_eq!(
+        get_toml(&root_manifest)["workspace"]["dependencies"]["libc"].as_str(),
+        Some("0.2.62")
+    );
+    assert_inherits_libc(&workspace_manifests[0]);
+    assert_inherits_libc(&workspace_manifests[1]);
+}
+
 #[test]
 #[cfg(feature = "test-external-apis")]
 fn upgrade_prints_messages() {
@@ -1,6 +1,10 @@
+use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::io::prelude::*;
-use std::{env, fs, path::Path, path::PathBuf, process};
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::{env, fs, path::Path, path::PathBuf, process, thread};
 
 /// Create temporary working directory with Cargo.toml manifest
 pub fn clone_out_test(source: &str) -> (tempdir::TempDir, String) {
@@ -19,6 +23,55 @@ pub fn clone_out_test(source: &str) -> (tempdir::TempDir, String) {
     (tmpdir, path)
 }
 
+/// Helper function that copies the workspace test into a temporary directory.
+pub fn copy_workspace_test() -> (tempdir::TempDir, String, Vec<String>) {
+    // Create a temporary directory and copy in the root manifest, the dummy rust file, and
+    // workspace member manifests.
+    let tmpdir = tempdir::TempDir::new("upgrade_workspace")
+        .expect("failed to construct temporary directory");
+
+    let (root_manifest_path, workspace_manifest_paths) = {
+        // Helper to copy in files to the temporary workspace. The standard library doesn't have a
+        // good equivalent of `cp -r`, hence this oddity.
+        let copy_in = |dir, file| {
+            let file_path = tmpdir
+                .path()
+                .join(dir)
+                .join(file)
+                .to_str()
+                .unwrap()
+                .to_string();
+
+            fs::create_dir_all(tmpdir.path().join(dir)).unwrap();
+
+            fs::copy(
+                format!("tests/fixtures/workspace/{}/{}", dir, file),
+                &file_path,
+            )
+            .unwrap_or_else(|err| panic!("could not copy test file: {}", err));
+
+            file_path
+        };
+
+        let root_manifest_path = copy_in(".", "Cargo.toml");
+        copy_in(".", "dummy.rs");
+        copy_in(".", "Cargo.lock");
+
+        let workspace_manifest_paths = ["one", "two", "implicit/three", "explicit/four"]
+            .iter()
+            .map(|member| copy_in(member, "Cargo.toml"))
+            .collect::<Vec<_>>();
+
+        (root_manifest_path, workspace_manifest_paths)
+    };
+
+    (
+        tmpdir,
+        root_manifest_path,
+        workspace_manifest_paths.to_owned(),
+    )
+}
+
 /// Add directory
 pub fn setup_alt_registry_config(path: &std::path::Path) {
     fs::create_dir(path.join(".cargo")).expect("failed to create .cargo directory");
@@ -29,17 +82,18 @@ pub fn setup_alt_registry_config(path: &std::path::Path) {
     .unwrap_or_else(|err| panic!("could not copy test cargo config: {}", err));
 }
 
-/// Execute local cargo command, includes `--manifest-path`, expect command failed
+/// Execute local cargo command, includes `--manifest-path`, expect command failed. Crate
+/// versions are looked up in the test registry, see `setup_test_index`.
 pub fn execute_bad_command<S>(command: &[S], manifest: &str)
 where
     S: AsRef<OsStr>,
 {
     let subcommand_name = &command[0].as_ref();
+    setup_test_index(Path::new(manifest).parent().unwrap());
 
     let call = process::Command::new(&get_command_path(subcommand_name))
         .args(command)
         .arg(format!("--manifest-path={}", manifest))
-        .env("CARGO_IS_TEST", "1")
         .output()
         .unwrap();
 
@@ -54,17 +108,50 @@ where
     }
 }
 
-/// Execute local cargo command, includes `--manifest-path`
+/// Execute local cargo command, includes `--manifest-path`. Crate versions are looked up in the
+/// test registry, see `setup_test_index`.
 pub fn execute_command<S>(command: &[S], manifest: &str)
 where
     S: AsRef<OsStr>,
 {
     let subcommand_name = &command[0].as_ref();
+    setup_test_index(Path::new(manifest).parent().unwrap());
+
+    let call = process::Command::new(&get_command_path(subcommand_name))
+        .args(command)
+        .arg(format!("--manifest-path={}", manifest))
+        .output()
+        .expect("call to test build failed");
+
+    if !call.status.success() {
+        println!("Status code: {:?}", call.status);
+        println!("STDOUT: {}", String::from_utf8_lossy(&call.stdout));
+        println!("STDERR: {}", String::from_utf8_lossy(&call.stderr));
+        panic!(
+            "cargo-{} failed to execute",
+            subcommand_name.to_string_lossy()
+        )
+    }
+}
+
+/// Execute local cargo command, includes `--manifest-path`, looking crate versions up in the
+/// registries configured for `manifest` instead of the test registry, with the environment
+/// variables `envs` set
+pub fn execute_command_with_registries<S>(
+    command: &[S],
+    manifest: &str,
+    cargo_home: &Path,
+    envs: &[(&str, &str)],
+) where
+    S: AsRef<OsStr>,
+{
+    let subcommand_name = &command[0].as_ref();
 
     let call = process::Command::new(&get_command_path(subcommand_name))
         .args(command)
         .arg(format!("--manifest-path={}", manifest))
-        .env("CARGO_IS_TEST", "1")
+        .env("CARGO_HOME", cargo_home)
+        .envs(envs.iter().cloned())
         .output()
         .expect("call to test build failed");
 
@@ -79,16 +166,17 @@ where
     }
 }
 
-/// Execute local cargo command in a given directory
+/// Execute local cargo command in a given directory. Crate versions are looked up in the test
+/// registry, see `setup_test_index`.
 pub fn execute_command_in_dir<S>(command: &[S], dir: &Path)
 where
     S: AsRef<OsStr>,
 {
     let subcommand_name = &command[0].as_ref();
+    setup_test_index(dir);
 
     let call = process::Command::new(&get_command_path(subcommand_name))
         .args(command)
-        .env("CARGO_IS_TEST", "1")
         .current_dir(dir)
         .output()
         .expect("call to test build failed");
@@ -112,6 +200,49 @@ pub fn get_toml(manifest_path: &str) -> toml_edit::Document {
     s.parse().expect("toml parse error")
 }
 
+/// Replace crates.io, and the registries configured in `dir/.cargo/config`, with the
+/// `local-registry` source in `tests/fixtures/registry`, by adding to that config file. Commands
+/// run on manifests in `dir` or below then look crate versions up in its index.
+pub fn setup_test_index(dir: &Path) {
+    let config_path = dir.join(".cargo").join("config");
+    let mut config = fs::read_to_string(&config_path).unwrap_or_default();
+    if config.contains("[source.test-registry]") {
+        return;
+    }
+
+    let registries = config
+        .parse::<toml_edit::Document>()
+        .expect("invalid test cargo config")["registries"]
+        .as_table()
+        .map(|registries| {
+            registries
+                .iter()
+                .filter_map(|(name, registry)| {
+                    Some((name.to_owned(), registry["index"].as_str()?.to_owned()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    config.push_str("\n[source.crates-io]\nreplace-with = \"test-registry\"\n");
+    for (name, index) in registries {
+        config.push_str(&format!(
+            "\n[source.{}]\nregistry = {:?}\nreplace-with = \"test-registry\"\n",
+            name, index
+        ));
+    }
+    let registry = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("registry");
+    config.push_str(&format!(
+        "\n[source.test-registry]\nlocal-registry = {:?}\n",
+        registry.to_str().unwrap()
+    ));
+
+    fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+    fs::write(config_path, config).unwrap();
+}
+
 pub fn get_command_path(s: impl AsRef<OsStr>) -> String {
     let target_dir: PathBuf = match env::var_os("CARGO_TARGET_DIR") {
         Some(dir) => dir.into(),
@@ -130,3 +261,116 @@ pub fn get_command_path(s: impl AsRef<OsStr>) -> String {
         .unwrap()
         .to_string()
 }
+
+/// A stand-in for a sparse registry index, serving files over HTTP from a local port.
+pub struct IndexServer {
+    /// The URL of the index, without the `sparse+` prefix
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl IndexServer {
+    /// Serve `files`, given as pairs of index paths and contents, until the test ends.
+    ///
+    /// Every file has the `ETag` `"1"`, so requests revalidating it get `304 Not Modified`.
+    pub fn start(files: &[(&str, &str)]) -> IndexServer {
+        IndexServer::start_with_token(files, None)
+    }
+
+    /// Like `start`, but answer `401 Unauthorized` to requests without `token` as their
+    /// `Authorization` header.
+    pub fn start_authenticated(files: &[(&str, &str)], token: &str) -> IndexServer {
+        IndexServer::start_with_token(files, Some(token))
+    }
+
+    fn start_with_token(files: &[(&str, &str)], token: Option<&str>) -> IndexServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind index server");
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let files = files
+            .iter()
+            .map(|(path, content)| (format!("/{}", path), content.to_string()))
+            .collect::<HashMap<_, _>>();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let authorization =
+            token.map(|token| format!("authorization: {}\r\n", token.to_lowercase()));
+
+        let log = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line.to_lowercase());
+                }
+                let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+                log.lock().unwrap().push(request.clone());
+
+                let authorized = authorization
+                    .as_ref()
+                    .map_or(true, |authorization| request.contains(authorization));
+                let response = match files.get(&path) {
+                    _ if !authorized => "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\
+                                         Connection: close\r\n\r\n"
+                        .to_string(),
+                    Some(_) if request.contains("if-none-match: \"1\"") => {
+                        "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                    }
+                    Some(content) => format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"1\"\r\nContent-Length: {}\r\n\
+                         Connection: close\r\n\r\n{}",
+                        content.len(),
+                        content
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\
+                             Connection: close\r\n\r\n"
+                        .to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        IndexServer { url, requests }
+    }
+
+    /// Get the heads of all requests received so far, in lowercase.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Create a bare git repository in `dir` serving `files`, given as pairs of paths and contents, on
+/// its default branch `main`, e.g. a registry index. Returns the URL of the repository.
+pub fn create_git_repo(dir: &Path, files: &[(&str, &str)]) -> String {
+    let work_dir = dir.join("index-work");
+    let mut opts = git2::RepositoryInitOptions::new();
+    opts.initial_head("main");
+    let repo = git2::Repository::init_opts(&work_dir, &opts).unwrap();
+    for (path, content) in files {
+        let path = work_dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = git2::Signature::now("test", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "index", &tree, &[])
+        .unwrap();
+
+    let bare_dir = dir.join("index.git");
+    git2::build::RepoBuilder::new()
+        .bare(true)
+        .clone(work_dir.to_str().unwrap(), &bare_dir)
+        .unwrap();
+    url::Url::from_directory_path(bare_dir).unwrap().to_string()
+}